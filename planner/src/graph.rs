@@ -0,0 +1,478 @@
+use std::collections::{BTreeMap, HashSet as Set};
+use std::fmt;
+use std::path::Path;
+
+use glam::Vec2;
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::migrate::{migrate, version, CURRENT_VERSION};
+use crate::obstacle::{segment_blocked, Obstacle};
+
+// Tags every graph has, with their default colors
+const DEFAULT_TAGS: [(&str, Color); 6] = [
+    ("amp", Color::rgb(255, 215, 0)),
+    ("climb", Color::rgb(255, 20, 147)),
+    ("intake", Color::rgb(238, 130, 238)),
+    ("shoot", Color::rgb(255, 165, 0)),
+    ("source", Color::rgb(64, 224, 208)),
+    ("staging", Color::rgb(192, 192, 192)),
+];
+
+/// A navigation graph of the field: where the robot can drive, and what it must avoid.
+///
+/// It's serialized in the current version of the graph file format, and deserialized from any
+/// version.
+#[derive(Clone, Debug)]
+pub struct FieldGraph {
+    pub sg: SpatialGraph,
+    /// Named sets of special nodes, such as shooting or intake positions.
+    pub tags: BTreeMap<String, NodeTag>,
+    pub obstacles: Vec<Obstacle>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NodeTag {
+    pub color: Color,
+    pub nodes: Set<NodeId>,
+}
+
+/// On-disk form of [`FieldGraph`], in the current version of the format.
+#[derive(Deserialize)]
+struct FieldGraphRepr {
+    #[serde(flatten)]
+    sg: SpatialGraph,
+    #[serde(default)]
+    tags: BTreeMap<String, NodeTag>,
+    #[serde(default)]
+    obstacles: Vec<Obstacle>,
+}
+
+impl From<FieldGraphRepr> for FieldGraph {
+    fn from(repr: FieldGraphRepr) -> Self {
+        let mut tags = repr.tags;
+        for (name, color) in DEFAULT_TAGS {
+            tags.entry(name.to_owned()).or_insert_with(|| NodeTag {
+                color,
+                nodes: Set::new(),
+            });
+        }
+        Self {
+            sg: repr.sg,
+            tags,
+            obstacles: repr.obstacles,
+        }
+    }
+}
+
+impl Serialize for FieldGraph {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Versioned<'a> {
+            version: u32,
+            #[serde(flatten)]
+            sg: &'a SpatialGraph,
+            tags: &'a BTreeMap<String, NodeTag>,
+            obstacles: &'a [Obstacle],
+        }
+        Versioned {
+            version: CURRENT_VERSION,
+            sg: &self.sg,
+            tags: &self.tags,
+            obstacles: &self.obstacles,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldGraph {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut json = Value::deserialize(deserializer)?;
+        migrate(&mut json).map_err(D::Error::custom)?;
+        FieldGraphRepr::deserialize(json)
+            .map(Self::from)
+            .map_err(D::Error::custom)
+    }
+}
+
+impl FieldGraph {
+    /// Names of the tags the node has.
+    pub fn node_tags(&self, node: NodeId) -> impl Iterator<Item = &str> + '_ {
+        self.tags
+            .iter()
+            .filter(move |(_, tag)| tag.nodes.contains(&node))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Positions of all nodes and obstacle vertices, for converting between coordinate frames.
+    pub fn points_mut(&mut self) -> impl Iterator<Item = &mut Vec2> {
+        self.sg.nodes.values_mut().chain(
+            self.obstacles
+                .iter_mut()
+                .flat_map(|obstacle| obstacle.vertices.iter_mut()),
+        )
+    }
+
+    /// Whether the edge passes through an obstacle, so that the robot can't use it.
+    pub fn edge_blocked(&self, edge: &Edge) -> bool {
+        segment_blocked(
+            &self.obstacles,
+            self.sg.nodes[&edge.start],
+            self.sg.nodes[&edge.end],
+        )
+    }
+}
+
+/// Persistent node identifier. Unlike a position in a list, it stays the same when other nodes are deleted.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NodeId(pub u32);
+
+/// Nodes and the edges between them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "SpatialGraphRepr", into = "SpatialGraphRepr")]
+pub struct SpatialGraph {
+    pub nodes: BTreeMap<NodeId, Vec2>,
+    pub edges: Vec<Edge>,
+    /// Lowest ID that has never been used, so that IDs of deleted nodes are not handed out again.
+    next_id: u32,
+}
+
+/// On-disk form of [`SpatialGraph`].
+#[derive(Serialize, Deserialize)]
+struct SpatialGraphRepr {
+    nodes: Vec<NodeRepr>,
+    edges: Vec<Edge>,
+    #[serde(default)]
+    next_id: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeRepr {
+    id: NodeId,
+    pos: Vec2,
+}
+
+impl From<SpatialGraphRepr> for SpatialGraph {
+    fn from(repr: SpatialGraphRepr) -> Self {
+        let nodes: BTreeMap<_, _> = repr
+            .nodes
+            .into_iter()
+            .map(|node| (node.id, node.pos))
+            .collect();
+        let min_next_id = nodes.keys().next_back().map_or(0, |id| id.0 + 1);
+        Self {
+            nodes,
+            edges: repr.edges,
+            next_id: repr.next_id.max(min_next_id),
+        }
+    }
+}
+
+impl From<SpatialGraph> for SpatialGraphRepr {
+    fn from(graph: SpatialGraph) -> Self {
+        Self {
+            nodes: graph
+                .nodes
+                .into_iter()
+                .map(|(id, pos)| NodeRepr { id, pos })
+                .collect(),
+            edges: graph.edges,
+            next_id: graph.next_id,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "EdgeRepr", into = "EdgeRepr")]
+pub struct Edge {
+    pub start: NodeId,
+    pub end: NodeId,
+    /// If set, the edge may only be traversed from `start` to `end`.
+    pub one_way: bool,
+    /// Factor applied to the cost of traversing the edge, e.g. to avoid congested zones.
+    pub cost_multiplier: f32,
+    /// Speed limit on the edge in m/s, e.g. for tight gaps near field elements.
+    pub max_speed: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EdgeRepr {
+    // Plain two-way edges are stored as just their nodes
+    Plain(NodeId, NodeId),
+    WithAttrs {
+        nodes: (NodeId, NodeId),
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        one_way: bool,
+        #[serde(default = "default_cost_multiplier")]
+        cost_multiplier: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_speed: Option<f32>,
+    },
+}
+
+fn default_cost_multiplier() -> f32 {
+    1.0
+}
+
+impl From<EdgeRepr> for Edge {
+    fn from(repr: EdgeRepr) -> Self {
+        match repr {
+            EdgeRepr::Plain(start, end) => Self::new(start, end),
+            EdgeRepr::WithAttrs {
+                nodes: (start, end),
+                one_way,
+                cost_multiplier,
+                max_speed,
+            } => Self {
+                start,
+                end,
+                one_way,
+                cost_multiplier,
+                max_speed,
+            },
+        }
+    }
+}
+
+impl From<Edge> for EdgeRepr {
+    fn from(edge: Edge) -> Self {
+        if edge.has_default_attrs() {
+            Self::Plain(edge.start, edge.end)
+        } else {
+            Self::WithAttrs {
+                nodes: (edge.start, edge.end),
+                one_way: edge.one_way,
+                cost_multiplier: edge.cost_multiplier,
+                max_speed: edge.max_speed,
+            }
+        }
+    }
+}
+
+impl Edge {
+    /// Creates a two-way edge without cost or speed adjustments.
+    pub fn new(start: NodeId, end: NodeId) -> Self {
+        Self {
+            start,
+            end,
+            one_way: false,
+            cost_multiplier: 1.0,
+            max_speed: None,
+        }
+    }
+
+    /// Whether the edge is a plain two-way edge.
+    pub fn has_default_attrs(&self) -> bool {
+        !self.one_way && !self.has_cost_attrs()
+    }
+
+    /// Whether the edge has a cost multiplier or speed limit.
+    pub fn has_cost_attrs(&self) -> bool {
+        self.cost_multiplier != 1.0 || self.max_speed.is_some()
+    }
+
+    /// Whether the edge joins the two nodes, in either order.
+    pub fn connects(&self, a: NodeId, b: NodeId) -> bool {
+        (self.start, self.end) == (a, b) || (self.start, self.end) == (b, a)
+    }
+
+    pub fn touches(&self, node: NodeId) -> bool {
+        self.start == node || self.end == node
+    }
+
+    /// The node that can be reached from `node` along this edge, if any.
+    pub fn successor(&self, node: NodeId) -> Option<NodeId> {
+        if self.start == node {
+            Some(self.end)
+        } else if self.end == node && !self.one_way {
+            Some(self.start)
+        } else {
+            None
+        }
+    }
+
+    /// The same edge pointing the other way.
+    pub fn reversed(self) -> Self {
+        Self {
+            start: self.end,
+            end: self.start,
+            ..self
+        }
+    }
+}
+
+impl SpatialGraph {
+    /// Adds a node with a fresh ID.
+    pub fn add_node(&mut self, pos: Vec2) -> NodeId {
+        let id = self.new_id();
+        self.nodes.insert(id, pos);
+        id
+    }
+
+    /// Reserves a fresh ID without adding a node.
+    pub fn new_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Finds a given edge, if it exists. Disregards order of node IDs in tuple, and edge direction.
+    pub fn find_edge(&self, (start, end): (NodeId, NodeId)) -> Option<usize> {
+        self.edges.iter().position(|edge| edge.connects(start, end))
+    }
+
+    /// Indices of all edges touching the given node.
+    pub fn connected_edges(&self, node: NodeId) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.edges
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(move |(_, edge)| edge.touches(node))
+            .map(|tup| tup.0)
+    }
+}
+
+impl FieldGraph {
+    /// Parses a graph in the JSON file format. Older versions of the format are migrated.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let value: Value = serde_json::from_str(json)?;
+        // Files in the current version are read straight from the text, so that errors have a line number
+        if version(&value) == Ok(CURRENT_VERSION) {
+            serde_json::from_str::<FieldGraphRepr>(json).map(Self::from)
+        } else {
+            serde_json::from_value(value)
+        }
+    }
+
+    /// The graph in the current version of the JSON file format.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// The graph in version 0 of the file format, for tools that haven't caught up:
+    /// nodes are listed by position only, and only shoot tags are kept. Edge attributes,
+    /// other tags and obstacles are left out.
+    pub fn to_legacy_json(&self) -> String {
+        let indices: BTreeMap<NodeId, usize> = self
+            .sg
+            .nodes
+            .keys()
+            .enumerate()
+            .map(|(i, &id)| (id, i))
+            .collect();
+        let mut shoot_idxs: Vec<usize> = self.tags.get("shoot").map_or(Vec::new(), |tag| {
+            tag.nodes
+                .iter()
+                .filter_map(|id| indices.get(id).copied())
+                .collect()
+        });
+        shoot_idxs.sort();
+        let json = serde_json::json!({
+            "nodes": self.sg.nodes.values().collect::<Vec<_>>(),
+            "edges": self
+                .sg
+                .edges
+                .iter()
+                .filter_map(|edge| Some([*indices.get(&edge.start)?, *indices.get(&edge.end)?]))
+                .collect::<Vec<_>>(),
+            "shoot_idxs": shoot_idxs,
+        });
+        serde_json::to_string_pretty(&json).unwrap()
+    }
+
+    /// Reads a graph file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let json = std::fs::read_to_string(path).map_err(LoadError::Io)?;
+        Self::from_json(&json).map_err(LoadError::Parse)
+    }
+
+    /// Writes the graph to a file, replacing it if it exists.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+}
+
+/// Why a graph file couldn't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// An sRGB color, stored in files as a hex string such as `"#FFA500"`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    /// Parses `RGB`, `RGBA`, `RRGGBB` or `RRGGBBAA` hex digits, optionally preceded by `#`.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if !hex.is_ascii() {
+            return None;
+        }
+        let digits: Vec<u8> = match hex.len() {
+            // Short forms repeat each digit
+            3 | 4 => hex
+                .chars()
+                .map(|c| c.to_digit(16).map(|d| d as u8 * 17))
+                .collect::<Option<_>>()?,
+            6 | 8 => (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+        Some(Self {
+            r: digits[0],
+            g: digits[1],
+            b: digits[2],
+            a: digits.get(3).copied().unwrap_or(255),
+        })
+    }
+
+    /// `#RRGGBB`, or `#RRGGBBAA` if the color isn't opaque.
+    pub fn to_hex(&self) -> String {
+        let rgb = format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b);
+        if self.a == 255 {
+            rgb
+        } else {
+            format!("{rgb}{:02X}", self.a)
+        }
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Self::from_hex(&hex).ok_or_else(|| D::Error::custom(format!("invalid hex color {hex:?}")))
+    }
+}
@@ -0,0 +1,47 @@
+//! Path planning over FRC field navigation graphs, independent of any game engine.
+//!
+//! A [`FieldGraph`] is a set of nodes joined by edges, along with named node tags and polygonal
+//! obstacles. It's read from and written to the JSON graph file format with [`FieldGraph::load`]
+//! and [`FieldGraph::save`]; older versions of the format are migrated when loaded.
+//! [`FieldGraph::validate`] reports problems such as edges to missing nodes
+//! ([`FieldGraph::load_checked`] also says where in the file each one is), and
+//! [`FieldGraph::find_path`] (or [`compute_path`] for more options) plans the fastest route
+//! between two points. Paths can be exported for PathPlanner with [`PathPlannerPath`], and
+//! PathPlanner's [`NavGrid`] can be turned into obstacles or checked against with
+//! [`FieldGraph::check_navgrid`].
+//! All positions are in m, in whatever frame the graph file uses.
+//!
+//! ```no_run
+//! use robot_planner::{clearance, FieldGraph, Vec2};
+//!
+//! let graph = FieldGraph::load("graph.json").unwrap();
+//! assert!(graph.validate().iter().all(|problem| problem.is_warning()));
+//! // A robot with 0.6 m bumpers that drives at up to 4 m/s
+//! let path = graph.find_path(Vec2::new(1.0, 1.0), Vec2::new(5.0, 3.0), clearance(0.6), 4.0);
+//! for waypoint in path.unwrap() {
+//!     println!("{}", waypoint.pos);
+//! }
+//! ```
+
+mod graph;
+mod locate;
+mod migrate;
+mod navgrid;
+mod obstacle;
+mod path;
+mod pathplanner;
+mod smoothing;
+mod validate;
+mod visibility;
+
+pub use glam::Vec2;
+
+pub use graph::{Color, Edge, FieldGraph, LoadError, NodeId, NodeTag, SpatialGraph};
+pub use migrate::CURRENT_VERSION;
+pub use navgrid::NavGrid;
+pub use obstacle::{segment_blocked, Obstacle};
+pub use path::{compute_path, norm_angle, path_length, Waypoint};
+pub use pathplanner::{PathConstraints, PathPlannerPath, TooFewWaypoints};
+pub use smoothing::smooth_path;
+pub use validate::{FileProblem, Location, Problem};
+pub use visibility::{clearance, VisibilityGraph};
@@ -0,0 +1,134 @@
+//! Finding where values are in JSON text, to point at problems in graph files.
+
+/// A JSON value and where it starts in the text.
+pub(crate) struct Spanned {
+    /// Byte offset
+    pub offset: usize,
+    pub value: Value,
+}
+
+pub(crate) enum Value {
+    Object(Vec<(String, Spanned)>),
+    Array(Vec<Spanned>),
+    /// Strings, numbers, booleans and null, as written in the text.
+    Scalar(String),
+}
+
+impl Spanned {
+    /// Parses JSON text that is already known to be valid.
+    pub fn parse(json: &str) -> Option<Self> {
+        Parser { text: json, pos: 0 }.value()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Spanned> {
+        match &self.value {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Elements of an array, or nothing if it isn't one.
+    pub fn elements(&self) -> &[Spanned] {
+        match &self.value {
+            Value::Array(elements) => elements,
+            _ => &[],
+        }
+    }
+
+    /// Whether the value is the given whole number.
+    pub fn is_number(&self, n: u32) -> bool {
+        matches!(&self.value, Value::Scalar(s) if s.parse() == Ok(n))
+    }
+}
+
+/// 1-based line and column of a byte offset in the text.
+pub(crate) fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Option<Spanned> {
+        self.skip_whitespace();
+        let offset = self.pos;
+        let value = match self.peek()? {
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                while self.next_item(b'}')? {
+                    let key = serde_json::from_str(self.string()?).ok()?;
+                    self.skip_whitespace();
+                    if self.peek()? != b':' {
+                        return None;
+                    }
+                    self.pos += 1;
+                    fields.push((key, self.value()?));
+                }
+                Value::Object(fields)
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut elements = Vec::new();
+                while self.next_item(b']')? {
+                    elements.push(self.value()?);
+                }
+                Value::Array(elements)
+            }
+            b'"' => Value::Scalar(self.string()?.to_owned()),
+            _ => {
+                let len = self.text[self.pos..]
+                    .find(|c: char| c == ',' || c == ']' || c == '}' || c.is_whitespace())
+                    .unwrap_or(self.text.len() - self.pos);
+                self.pos += len;
+                Value::Scalar(self.text[offset..self.pos].to_owned())
+            }
+        };
+        Some(Spanned { offset, value })
+    }
+
+    // Moves to the next item of an object or array, or past its end, returning whether there is one
+    fn next_item(&mut self, close: u8) -> Option<bool> {
+        self.skip_whitespace();
+        if self.peek()? == b',' {
+            self.pos += 1;
+            self.skip_whitespace();
+        }
+        if self.peek()? == close {
+            self.pos += 1;
+            return Some(false);
+        }
+        Some(true)
+    }
+
+    // A string, including its quotes
+    fn string(&mut self) -> Option<&str> {
+        let start = self.pos;
+        self.pos += 1;
+        loop {
+            match self.peek()? {
+                b'\\' => self.pos += 2,
+                b'"' => break,
+                _ => self.pos += 1,
+            }
+        }
+        self.pos += 1;
+        Some(&self.text[start..self.pos])
+    }
+}
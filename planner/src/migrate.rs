@@ -0,0 +1,78 @@
+//! Upgrading graph files written in older versions of the format.
+//!
+//! Files didn't have a `version` field until version 2, so the version of older ones is told from
+//! their shape:
+//! - Version 0 lists nodes as bare positions and refers to them by index, in edges and in
+//!   `shoot_idxs`.
+//! - Version 1 gives nodes persistent IDs (`{"id", "pos"}` and `next_id`), lets edges have
+//!   attributes, and lists shoot nodes by ID in `shoot_ids`.
+//! - Version 2 replaces shoot nodes with named, colored `tags`, and adds `obstacles`.
+
+use serde_json::{json, Map, Value};
+
+/// Version of the graph file format that is written.
+pub const CURRENT_VERSION: u32 = 2;
+
+// Each one upgrades a file from the version at its index to the next
+const MIGRATIONS: [fn(&mut Map<String, Value>); CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+/// Version of the format the file was written in.
+pub(crate) fn version(json: &Value) -> Result<u32, String> {
+    if let Some(version) = json.get("version") {
+        return version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("invalid version {version}"));
+    }
+    let first_node = json.get("nodes").and_then(|nodes| nodes.get(0));
+    Ok(
+        if first_node.is_some_and(Value::is_array) || json.get("shoot_idxs").is_some() {
+            0
+        } else if json.get("shoot_ids").is_some() {
+            1
+        } else {
+            2
+        },
+    )
+}
+
+/// Upgrades a graph file to the current version of the format.
+pub(crate) fn migrate(json: &mut Value) -> Result<(), String> {
+    let version = version(json)?;
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "graph file is version {version}, but only versions up to {CURRENT_VERSION} can be read"
+        ));
+    }
+    let Value::Object(fields) = json else {
+        return Err("graph file isn't a JSON object".to_owned());
+    };
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(fields);
+    }
+    fields.insert("version".to_owned(), CURRENT_VERSION.into());
+    Ok(())
+}
+
+// Nodes get IDs equal to their old index, so that edges and shoot nodes keep referring to them
+fn v0_to_v1(fields: &mut Map<String, Value>) {
+    if let Some(Value::Array(nodes)) = fields.get_mut("nodes") {
+        for (i, node) in nodes.iter_mut().enumerate() {
+            *node = json!({ "id": i, "pos": node.take() });
+        }
+        let next_id = nodes.len();
+        fields.insert("next_id".to_owned(), next_id.into());
+    }
+    if let Some(shoot) = fields.remove("shoot_idxs") {
+        fields.insert("shoot_ids".to_owned(), shoot);
+    }
+}
+
+fn v1_to_v2(fields: &mut Map<String, Value>) {
+    if let Some(shoot) = fields.remove("shoot_ids") {
+        fields.insert(
+            "tags".to_owned(),
+            json!({ "shoot": { "color": "#FFA500", "nodes": shoot } }),
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use glam::Vec2;
+use serde::Deserialize;
+
+use crate::graph::LoadError;
+use crate::obstacle::Obstacle;
+
+/// PathPlanner's occupancy grid of the field (its `navgrid.json`), which tells the robot's
+/// on-board pathfinder where it can drive.
+#[derive(Clone, Debug, Deserialize)]
+pub struct NavGrid {
+    /// Size of the field, in m.
+    #[serde(rename = "field_size", with = "size")]
+    pub field_size: Vec2,
+    /// Side length of each cell, in m.
+    #[serde(rename = "nodeSizeMeters")]
+    pub cell_size: f32,
+    /// Whether each cell is blocked, by row (along y) and then by column (along x).
+    pub grid: Vec<Vec<bool>>,
+}
+
+// Sizes are stored as {"x", "y"} objects
+mod size {
+    use glam::Vec2;
+    use serde::{Deserialize, Deserializer};
+
+    #[derive(Deserialize)]
+    struct Size {
+        x: f32,
+        y: f32,
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec2, D::Error> {
+        let size = Size::deserialize(deserializer)?;
+        Ok(Vec2::new(size.x, size.y))
+    }
+}
+
+impl NavGrid {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Reads a `navgrid.json` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let json = std::fs::read_to_string(path).map_err(LoadError::Io)?;
+        Self::from_json(&json).map_err(LoadError::Parse)
+    }
+
+    /// Whether the robot can't be at the point. Points off the grid are blocked.
+    pub fn is_blocked(&self, p: Vec2) -> bool {
+        let cell = (p / self.cell_size).floor();
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return true;
+        }
+        let row = self.grid.get(cell.y as usize);
+        row.and_then(|row| row.get(cell.x as usize))
+            .copied()
+            .unwrap_or(true)
+    }
+
+    /// Whether the segment from `a` to `b` passes through a blocked cell.
+    pub fn segment_blocked(&self, a: Vec2, b: Vec2) -> bool {
+        // Checking every quarter cell can't step over one
+        let steps = (a.distance(b) / (self.cell_size / 4.0)).ceil().max(1.0) as usize;
+        (0..=steps).any(|i| self.is_blocked(a.lerp(b, i as f32 / steps as f32)))
+    }
+
+    /// The blocked cells as rectangular obstacles, with runs of blocked cells in a row merged
+    /// together, and then with identical runs in the rows after it.
+    pub fn obstacles(&self) -> Vec<Obstacle> {
+        // First row of each rectangle that the current row may extend, by its columns
+        let mut open: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        let mut obstacles = Vec::new();
+        let mut close = |(start, end): (usize, usize), first_row: usize, end_row: usize| {
+            let s = self.cell_size;
+            let (x0, x1) = (start as f32 * s, end as f32 * s);
+            let (y0, y1) = (first_row as f32 * s, end_row as f32 * s);
+            obstacles.push(Obstacle {
+                vertices: vec![
+                    Vec2::new(x0, y0),
+                    Vec2::new(x1, y0),
+                    Vec2::new(x1, y1),
+                    Vec2::new(x0, y1),
+                ],
+            });
+        };
+        for (r, row) in self.grid.iter().enumerate() {
+            let mut runs = Vec::new();
+            let mut c = 0;
+            while c < row.len() {
+                if row[c] {
+                    let start = c;
+                    while c < row.len() && row[c] {
+                        c += 1;
+                    }
+                    runs.push((start, c));
+                } else {
+                    c += 1;
+                }
+            }
+            let mut next_open = BTreeMap::new();
+            for run in runs {
+                next_open.insert(run, open.remove(&run).unwrap_or(r));
+            }
+            for (run, first_row) in std::mem::replace(&mut open, next_open) {
+                close(run, first_row, r);
+            }
+        }
+        for (run, first_row) in open {
+            close(run, first_row, self.grid.len());
+        }
+        obstacles
+    }
+}
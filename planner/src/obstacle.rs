@@ -0,0 +1,76 @@
+use glam::Vec2;
+
+use serde::{Deserialize, Serialize};
+
+/// A field element the robot can't drive through.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Obstacle {
+    pub vertices: Vec<Vec2>,
+}
+
+impl Obstacle {
+    /// Whether the point is inside the polygon.
+    pub fn contains(&self, p: Vec2) -> bool {
+        // Even-odd rule: count crossings of a ray going from `p` in the +x direction
+        self.sides()
+            .filter(|&(a, b)| (a.y > p.y) != (b.y > p.y))
+            .filter(|&(a, b)| p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x))
+            .count()
+            % 2
+            == 1
+    }
+
+    /// Whether the segment from `a` to `b` passes through the polygon.
+    /// Segments that only touch its boundary are not blocked.
+    pub fn blocks(&self, a: Vec2, b: Vec2) -> bool {
+        self.sides().any(|(c, d)| segments_cross(a, b, c, d)) || self.contains((a + b) / 2.0)
+    }
+
+    /// The polygon grown outwards by `margin`, along with which of its corners are convex.
+    /// Only convex corners are useful to path around, since a shortest path never bends at a concave one.
+    pub fn inflated(&self, margin: f32) -> (Obstacle, Vec<bool>) {
+        let n = self.vertices.len();
+        // Positive for counterclockwise polygons, whose outside is to the right of each side
+        let orientation = self
+            .sides()
+            .map(|(a, b)| a.perp_dot(b))
+            .sum::<f32>()
+            .signum();
+        let outward = |a: Vec2, b: Vec2| -(b - a).normalize_or_zero().perp() * orientation;
+        let (vertices, convex) = (0..n)
+            .map(|i| {
+                let (prev, v, next) = (
+                    self.vertices[(i + n - 1) % n],
+                    self.vertices[i],
+                    self.vertices[(i + 1) % n],
+                );
+                let (n1, n2) = (outward(prev, v), outward(v, next));
+                // Miter join, limited so that very sharp corners don't spike out too far
+                let bisector = (n1 + n2).normalize_or_zero();
+                let dist = (margin / bisector.dot(n1).max(0.1)).min(3.0 * margin);
+                let convex = (v - prev).perp_dot(next - v) * orientation > 0.0;
+                (v + bisector * dist, convex)
+            })
+            .unzip();
+        (Obstacle { vertices }, convex)
+    }
+
+    fn sides(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let n = self.vertices.len();
+        (0..n).map(move |i| (self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+}
+
+/// Whether the segment from `a` to `b` passes through any of the obstacles.
+pub fn segment_blocked(obstacles: &[Obstacle], a: Vec2, b: Vec2) -> bool {
+    obstacles.iter().any(|obstacle| obstacle.blocks(a, b))
+}
+
+/// Whether the segments properly intersect, i.e. cross at a single point that isn't an endpoint of either.
+pub(crate) fn segments_cross(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+    let (d1, d2) = (side(a, b, c), side(a, b, d));
+    let (d3, d4) = (side(c, d, a), side(c, d, b));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
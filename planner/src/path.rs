@@ -0,0 +1,203 @@
+use std::f32::consts::PI;
+
+use glam::Vec2;
+
+use pathfinding::directed::astar::astar;
+
+use crate::graph::{Edge, FieldGraph, NodeId, SpatialGraph};
+use crate::obstacle::{segment_blocked, Obstacle};
+
+/// A point along a planned path.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Waypoint {
+    pub pos: Vec2,
+    /// Speed limit on the segment leading to this waypoint, in m/s.
+    pub max_speed: Option<f32>,
+}
+
+// Number of nodes the start and end of a path are linked to
+const ATTACH_CANDIDATES: usize = 4;
+
+/// Finds the fastest path from `start` to `end` through the graph for a robot with the given top speed.
+/// Edges that pass within `clearance` of an obstacle (see [`clearance`](crate::clearance)) are
+/// never used, and neither are links from the start and to the end that do.
+///
+/// For a robot that has to turn in place at `turn_rate` to change direction, the time spent turning
+/// at each node, and from its current `heading` at the start, is counted too.
+///
+/// The graph mustn't have edges to nodes that don't exist; see [`FieldGraph::validate`].
+#[allow(clippy::too_many_arguments)]
+pub fn compute_path(
+    start: Vec2,
+    end: Vec2,
+    graph: &SpatialGraph,
+    obstacles: &[Obstacle],
+    clearance: f32,
+    max_speed: f32,
+    turn_rate: Option<f32>,
+    heading: f32,
+) -> Option<Vec<Waypoint>> {
+    // Keep the whole robot clear of obstacles, not just its center
+    let obstacles: Vec<_> = obstacles
+        .iter()
+        .filter(|obstacle| obstacle.vertices.len() >= 3)
+        .map(|obstacle| obstacle.inflated(clearance).0)
+        .collect();
+    let obstacles = &obstacles[..];
+    // Link the start and end to the few nearest nodes they can see, rather than just the nearest
+    // one, so that the search can pick whichever gives the best overall route
+    let visible_nodes = |p: Vec2| {
+        let mut nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .filter(|&(_, &node)| !segment_blocked(obstacles, p, node))
+            .map(|(&id, &node)| (id, (node - p).length()))
+            .collect();
+        nodes.sort_by(|a, b| a.1.total_cmp(&b.1));
+        nodes.truncate(ATTACH_CANDIDATES);
+        nodes.into_iter().map(|t| t.0)
+    };
+    let one_way = |start, end| Edge {
+        one_way: true,
+        ..Edge::new(start, end)
+    };
+    let (start_links, end_links) = (visible_nodes(start), visible_nodes(end));
+
+    let mut graph = graph.clone();
+    let start_id = graph.add_node(start);
+    let end_id = graph.add_node(end);
+    graph
+        .edges
+        .extend(start_links.map(|id| one_way(start_id, id)));
+    graph.edges.extend(end_links.map(|id| one_way(id, end_id)));
+    if !segment_blocked(obstacles, start, end) {
+        graph.edges.push(one_way(start_id, end_id));
+    }
+
+    // Costs are traversal times, so that edge speed limits are accounted for along with cost multipliers
+    let time_cost = |dist: f32, speed: f32| ((dist / speed) * 1e5).round() as u32;
+    let edge_cost = |from: NodeId, edge: &Edge| {
+        let to = edge.successor(from)?;
+        if segment_blocked(obstacles, graph.nodes[&from], graph.nodes[&to]) {
+            return None;
+        }
+        let dist = (graph.nodes[&to] - graph.nodes[&from]).length() * edge.cost_multiplier;
+        let speed = edge.max_speed.map_or(max_speed, |s| s.min(max_speed));
+        Some((to, time_cost(dist, speed)))
+    };
+
+    // Searching over (node, previous node) pairs when turning counts, so that the direction the robot
+    // arrives from is known
+    let successors = |&(id, prev): &(NodeId, Option<NodeId>)| {
+        let pos = graph.nodes[&id];
+        let facing = match prev {
+            Some(prev) => (pos - graph.nodes[&prev]).to_angle(),
+            None => heading,
+        };
+        graph
+            .edges
+            .iter()
+            .filter_map(|edge| {
+                let (to, cost) = edge_cost(id, edge)?;
+                let Some(turn_rate) = turn_rate else {
+                    return Some(((to, None), cost));
+                };
+                let turn = norm_angle((graph.nodes[&to] - pos).to_angle() - facing).abs();
+                Some(((to, Some(id)), cost + time_cost(turn, turn_rate)))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Scaled by the smallest multiplier so that the heuristic never overestimates
+    let min_multiplier = graph
+        .edges
+        .iter()
+        .map(|edge| edge.cost_multiplier)
+        .fold(1.0, f32::min);
+    let heuristic = |(id, _): &(NodeId, Option<NodeId>)| {
+        time_cost((graph.nodes[id] - end).length() * min_multiplier, max_speed)
+    };
+
+    let path = astar(&(start_id, None), successors, heuristic, |&(id, _)| {
+        id == end_id
+    });
+
+    path.map(|(states, _)| {
+        let ids: Vec<_> = states.into_iter().map(|t| t.0).collect();
+        let first = Waypoint {
+            pos: graph.nodes[&ids[0]],
+            max_speed: None,
+        };
+        // Look up the speed limit of the cheapest edge between each pair of consecutive nodes,
+        // which is the one the search went through
+        let rest = ids.windows(2).map(|w| {
+            let max_speed = graph
+                .edges
+                .iter()
+                .filter_map(|edge| {
+                    let (to, cost) = edge_cost(w[0], edge)?;
+                    (to == w[1]).then_some((cost, edge.max_speed))
+                })
+                .min_by_key(|t| t.0)
+                .and_then(|t| t.1);
+            Waypoint {
+                pos: graph.nodes[&w[1]],
+                max_speed,
+            }
+        });
+        std::iter::once(first).chain(rest).collect()
+    })
+}
+
+impl FieldGraph {
+    /// Finds the fastest path from `start` to `end` for a robot that can drive in any direction
+    /// at up to `max_speed`, in m/s, keeping its center `clearance` from obstacles. See
+    /// [`compute_path`].
+    pub fn find_path(
+        &self,
+        start: Vec2,
+        end: Vec2,
+        clearance: f32,
+        max_speed: f32,
+    ) -> Option<Vec<Waypoint>> {
+        compute_path(
+            start,
+            end,
+            &self.sg,
+            &self.obstacles,
+            clearance,
+            max_speed,
+            None,
+            0.0,
+        )
+    }
+
+    /// Finds a path from `start` to the node with the given tag that is closest along the graph.
+    pub fn find_path_to_tag(
+        &self,
+        start: Vec2,
+        tag: &str,
+        clearance: f32,
+        max_speed: f32,
+    ) -> Option<Vec<Waypoint>> {
+        let mut nodes: Vec<_> = self.tags.get(tag)?.nodes.iter().copied().collect();
+        // So that ties are broken the same way every time
+        nodes.sort();
+        nodes
+            .into_iter()
+            .filter_map(|node| {
+                self.find_path(start, *self.sg.nodes.get(&node)?, clearance, max_speed)
+            })
+            .min_by(|a, b| path_length(a).total_cmp(&path_length(b)))
+    }
+}
+
+/// Length of the path through the waypoints, in m.
+pub fn path_length(path: &[Waypoint]) -> f32 {
+    path.windows(2).map(|w| w[0].pos.distance(w[1].pos)).sum()
+}
+
+/// The angle wrapped to between -π and π, in rad.
+pub fn norm_angle(a: f32) -> f32 {
+    (a + PI).rem_euclid(2.0 * PI) - PI
+}
@@ -0,0 +1,283 @@
+//! Exporting paths as PathPlanner `.path` files.
+
+use std::fmt;
+use std::path::Path;
+
+use glam::Vec2;
+use serde::Serialize;
+
+use crate::path::Waypoint;
+
+/// Version of the PathPlanner file format that is written.
+const FILE_VERSION: &str = "2025.0";
+// How far along each segment the control points next to an anchor are. A third keeps straight
+// stretches evenly paced.
+const CONTROL_FRACTION: f32 = 1.0 / 3.0;
+
+/// Limits PathPlanner holds the robot to along a path.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PathConstraints {
+    /// m/s
+    pub max_velocity: f32,
+    /// m/s²
+    pub max_acceleration: f32,
+    /// deg/s
+    pub max_angular_velocity: f32,
+    /// deg/s²
+    pub max_angular_acceleration: f32,
+}
+
+impl Default for PathConstraints {
+    /// PathPlanner's own defaults.
+    fn default() -> Self {
+        Self {
+            max_velocity: 3.0,
+            max_acceleration: 3.0,
+            max_angular_velocity: 540.0,
+            max_angular_acceleration: 720.0,
+        }
+    }
+}
+
+/// A path to export for PathPlanner.
+///
+/// The robot drives through the waypoints along a smooth curve, and faces where the path ends the
+/// whole way, like robots in the sim do. Speed limits of waypoints become constraint zones.
+#[derive(Clone, Debug)]
+pub struct PathPlannerPath {
+    /// At least two, in the field frame PathPlanner uses.
+    pub waypoints: Vec<Waypoint>,
+    pub constraints: PathConstraints,
+    /// Names that link the first and last waypoint to those of other paths with the same name,
+    /// for chains of paths that meet at the same points.
+    pub start_link: Option<String>,
+    pub end_link: Option<String>,
+}
+
+impl PathPlannerPath {
+    /// Fails if there are fewer than two waypoints, since a path needs a start and an end.
+    pub fn new(
+        waypoints: Vec<Waypoint>,
+        constraints: PathConstraints,
+    ) -> Result<Self, TooFewWaypoints> {
+        if waypoints.len() < 2 {
+            return Err(TooFewWaypoints(waypoints.len()));
+        }
+        Ok(Self {
+            waypoints,
+            constraints,
+            start_link: None,
+            end_link: None,
+        })
+    }
+
+    /// The path as the contents of a `.path` file.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_file()).unwrap()
+    }
+
+    /// Writes the path to a `.path` file, replacing it if it exists.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    fn to_file(&self) -> PathFile {
+        let points: Vec<Vec2> = self.waypoints.iter().map(|wp| wp.pos).collect();
+        let n = points.len();
+        let end = points[n - 1];
+        let waypoints = (0..n)
+            .map(|i| {
+                let p = points[i];
+                let prev = i.checked_sub(1).map(|j| points[j]);
+                let next = points.get(i + 1).copied();
+                // Tangents bisect corners, so that the curve passes through them smoothly
+                let tangent = match (prev, next) {
+                    (Some(a), Some(b)) => ((p - a).normalize_or_zero()
+                        + (b - p).normalize_or_zero())
+                    .try_normalize()
+                    .unwrap_or((b - p).normalize_or_zero()),
+                    (Some(a), None) => (p - a).normalize_or_zero(),
+                    (None, Some(b)) => (b - p).normalize_or_zero(),
+                    (None, None) => Vec2::ZERO,
+                };
+                let link = match i {
+                    0 => self.start_link.clone(),
+                    _ if i == n - 1 => self.end_link.clone(),
+                    _ => None,
+                };
+                PathWaypoint {
+                    anchor: p.into(),
+                    prev_control: prev
+                        .map(|a| (p - tangent * a.distance(p) * CONTROL_FRACTION).into()),
+                    next_control: next
+                        .map(|b| (p + tangent * p.distance(b) * CONTROL_FRACTION).into()),
+                    is_locked: false,
+                    linked_name: link,
+                }
+            })
+            .collect();
+
+        let rotation_targets = (1..n.saturating_sub(1))
+            .filter(|&i| points[i] != end)
+            .map(|i| RotationTarget {
+                waypoint_relative_pos: i as f32,
+                rotation_degrees: heading(points[i], end),
+            })
+            .collect();
+
+        // Runs of segments with the same speed limit. A waypoint's limit is on the way to it.
+        let mut constraint_zones: Vec<ConstraintZone> = Vec::new();
+        for i in 1..n {
+            let Some(max_velocity) = self.waypoints[i]
+                .max_speed
+                .filter(|&limit| limit < self.constraints.max_velocity)
+            else {
+                continue;
+            };
+            match constraint_zones.last_mut() {
+                Some(zone)
+                    if zone.max_waypoint_relative_pos == (i - 1) as f32
+                        && zone.constraints.max_velocity == max_velocity =>
+                {
+                    zone.max_waypoint_relative_pos = i as f32;
+                }
+                _ => constraint_zones.push(ConstraintZone {
+                    name: format!("Speed limit {max_velocity} m/s"),
+                    min_waypoint_relative_pos: (i - 1) as f32,
+                    max_waypoint_relative_pos: i as f32,
+                    constraints: Constraints {
+                        max_velocity,
+                        ..Constraints::from(self.constraints)
+                    },
+                }),
+            }
+        }
+
+        PathFile {
+            version: FILE_VERSION,
+            waypoints,
+            rotation_targets,
+            constraint_zones,
+            point_towards_zones: Vec::new(),
+            event_markers: Vec::new(),
+            global_constraints: self.constraints.into(),
+            goal_end_state: State {
+                velocity: 0.0,
+                rotation: n.checked_sub(2).map_or(0.0, |i| heading(points[i], end)),
+            },
+            reversed: false,
+            folder: None,
+            ideal_starting_state: State {
+                velocity: 0.0,
+                rotation: heading(points[0], end),
+            },
+            use_default_constraints: false,
+        }
+    }
+}
+
+/// The number of waypoints given for a path, which was less than two.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TooFewWaypoints(pub usize);
+
+impl fmt::Display for TooFewWaypoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a path needs at least 2 waypoints, but this one has {}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for TooFewWaypoints {}
+
+// Direction from one point to another, in degrees counterclockwise from the +x axis
+fn heading(from: Vec2, to: Vec2) -> f32 {
+    (to - from).to_angle().to_degrees()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PathFile {
+    version: &'static str,
+    waypoints: Vec<PathWaypoint>,
+    rotation_targets: Vec<RotationTarget>,
+    constraint_zones: Vec<ConstraintZone>,
+    point_towards_zones: Vec<serde_json::Value>,
+    event_markers: Vec<serde_json::Value>,
+    global_constraints: Constraints,
+    goal_end_state: State,
+    reversed: bool,
+    folder: Option<String>,
+    ideal_starting_state: State,
+    use_default_constraints: bool,
+}
+
+#[derive(Serialize)]
+struct Point {
+    x: f32,
+    y: f32,
+}
+
+impl From<Vec2> for Point {
+    fn from(p: Vec2) -> Self {
+        Self { x: p.x, y: p.y }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PathWaypoint {
+    anchor: Point,
+    prev_control: Option<Point>,
+    next_control: Option<Point>,
+    is_locked: bool,
+    linked_name: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RotationTarget {
+    waypoint_relative_pos: f32,
+    rotation_degrees: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConstraintZone {
+    name: String,
+    min_waypoint_relative_pos: f32,
+    max_waypoint_relative_pos: f32,
+    constraints: Constraints,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Constraints {
+    max_velocity: f32,
+    max_acceleration: f32,
+    max_angular_velocity: f32,
+    max_angular_acceleration: f32,
+    nominal_voltage: f32,
+    unlimited: bool,
+}
+
+impl From<PathConstraints> for Constraints {
+    fn from(c: PathConstraints) -> Self {
+        Self {
+            max_velocity: c.max_velocity,
+            max_acceleration: c.max_acceleration,
+            max_angular_velocity: c.max_angular_velocity,
+            max_angular_acceleration: c.max_angular_acceleration,
+            nominal_voltage: 12.0,
+            unlimited: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct State {
+    velocity: f32,
+    rotation: f32,
+}
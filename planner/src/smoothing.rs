@@ -0,0 +1,174 @@
+use glam::Vec2;
+
+use crate::graph::SpatialGraph;
+use crate::obstacle::{segment_blocked, segments_cross, Obstacle};
+use crate::path::Waypoint;
+
+/// Smallest radius of the curves put at path corners, in m, when there is room for it. Where there
+/// isn't, corners are rounded more tightly or left sharp, so whatever follows the path has to slow
+/// down for them by its curvature, like trajectories in the sim do with their centripetal
+/// acceleration limit.
+const MIN_TURN_RADIUS: f32 = 0.5;
+// Spacing of the waypoints sampled along corner curves, in m
+const SAMPLE_SPACING: f32 = 0.1;
+
+/// Removes waypoints that can be skipped by driving straight, then rounds off the remaining corners,
+/// keeping the robot's center `clearance` away from obstacles.
+///
+/// The path must have been found through `graph`. Its one-way edges and edges with a cost
+/// multiplier steer paths on purpose, so they are never skipped or cut across.
+pub fn smooth_path(
+    path: &[Waypoint],
+    graph: &SpatialGraph,
+    obstacles: &[Obstacle],
+    clearance: f32,
+) -> Vec<Waypoint> {
+    // Keep the whole robot clear of obstacles, not just its center
+    let inflated: Vec<_> = obstacles
+        .iter()
+        .filter(|obstacle| obstacle.vertices.len() >= 3)
+        .map(|obstacle| obstacle.inflated(clearance).0)
+        .collect();
+    round_corners(&shortcut(path, graph, &inflated), &inflated)
+}
+
+fn min_speed(a: Option<f32>, b: Option<f32>) -> Option<f32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        _ => a.or(b),
+    }
+}
+
+/// Greedily skips to the furthest waypoint that can be reached in a straight line, without skipping
+/// or crossing one-way or cost-multiplied edges. A shortcut takes the lowest speed limit of the
+/// segments it replaces.
+fn shortcut(path: &[Waypoint], graph: &SpatialGraph, obstacles: &[Obstacle]) -> Vec<Waypoint> {
+    let Some(&first) = path.first() else {
+        return Vec::new();
+    };
+    // Ends of the edges that aren't plain two-way edges
+    let restricted: Vec<(Vec2, Vec2)> = graph
+        .edges
+        .iter()
+        .filter(|edge| edge.one_way || edge.cost_multiplier != 1.0)
+        .filter_map(|edge| Some((*graph.nodes.get(&edge.start)?, *graph.nodes.get(&edge.end)?)))
+        .collect();
+    // Whether each segment of the path goes along one of them. Links to the start and end aren't
+    // graph edges, so they never do.
+    let joins = |(c, d): (Vec2, Vec2), a: Vec2, b: Vec2| (c, d) == (a, b) || (c, d) == (b, a);
+    let plain_edge = |a: Vec2, b: Vec2| {
+        graph.edges.iter().any(|edge| {
+            !edge.one_way
+                && edge.cost_multiplier == 1.0
+                && graph
+                    .nodes
+                    .get(&edge.start)
+                    .zip(graph.nodes.get(&edge.end))
+                    .is_some_and(|(&c, &d)| joins((c, d), a, b))
+        })
+    };
+    let fixed: Vec<bool> = path
+        .windows(2)
+        .map(|w| {
+            let (a, b) = (w[0].pos, w[1].pos);
+            !plain_edge(a, b) && restricted.iter().any(|&ends| joins(ends, a, b))
+        })
+        .collect();
+
+    let mut result = vec![first];
+    let mut i = 0;
+    while i + 1 < path.len() {
+        // Stop at the first segment that has to be kept
+        let last = (i..fixed.len())
+            .find(|&k| fixed[k])
+            .unwrap_or(path.len() - 1);
+        // The next waypoint is always reachable, since it's along a graph edge
+        let j = (i + 2..=last)
+            .rev()
+            .find(|&j| {
+                let (a, b) = (path[i].pos, path[j].pos);
+                !segment_blocked(obstacles, a, b)
+                    && !restricted.iter().any(|&(c, d)| segments_cross(a, b, c, d))
+            })
+            .unwrap_or(i + 1);
+        let max_speed = path[i + 1..=j]
+            .iter()
+            .map(|wp| wp.max_speed)
+            .reduce(min_speed)
+            .flatten();
+        result.push(Waypoint {
+            pos: path[j].pos,
+            max_speed,
+        });
+        i = j;
+    }
+    result
+}
+
+/// Replaces each corner with a quadratic Bézier curve, sampled into waypoints.
+fn round_corners(path: &[Waypoint], obstacles: &[Obstacle]) -> Vec<Waypoint> {
+    if path.len() < 3 {
+        return path.to_vec();
+    }
+    let mut result = vec![path[0]];
+    for k in 1..path.len() - 1 {
+        let (prev, corner, next) = (path[k - 1], path[k], path[k + 1]);
+        let (to_prev, to_next) = (prev.pos - corner.pos, next.pos - corner.pos);
+        let half_angle = to_prev.angle_between(to_next).abs() / 2.0;
+
+        // The curve's tightest point is its middle, where its radius is d * sin²(a/2) / cos(a/2)
+        // for control legs of length d meeting at angle a. Use the shortest legs that give the
+        // minimum radius, but don't take more than half of either segment.
+        let needed = MIN_TURN_RADIUS * half_angle.cos() / half_angle.sin().powi(2);
+        // Nothing to round off if the path (nearly) goes straight through
+        if !needed.is_finite() || needed < 0.01 {
+            result.push(corner);
+            continue;
+        }
+        let available = 0.5 * to_prev.length().min(to_next.length());
+        let mut legs = needed.min(available);
+
+        // Tighten the curve until it's clear of obstacles, or give up and keep the sharp corner
+        let mut samples = Vec::new();
+        for _ in 0..4 {
+            let (start, end) = (
+                corner.pos + to_prev.normalize_or_zero() * legs,
+                corner.pos + to_next.normalize_or_zero() * legs,
+            );
+            let n = ((2.0 * legs / SAMPLE_SPACING).ceil() as usize).max(2);
+            let points: Vec<_> = (0..=n)
+                .map(|i| {
+                    let t = i as f32 / n as f32;
+                    start.lerp(corner.pos, t).lerp(corner.pos.lerp(end, t), t)
+                })
+                .collect();
+            if points
+                .windows(2)
+                .all(|w| !segment_blocked(obstacles, w[0], w[1]))
+            {
+                // The first half of the curve is on the segment into the corner, the second half on the one out of it
+                samples = points
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, pos)| Waypoint {
+                        pos,
+                        max_speed: if 2 * i <= n {
+                            corner.max_speed
+                        } else {
+                            next.max_speed
+                        },
+                    })
+                    .collect();
+                break;
+            }
+            legs /= 2.0;
+        }
+        if samples.is_empty() {
+            result.push(corner);
+        } else {
+            result.extend(samples);
+        }
+    }
+    result.push(path[path.len() - 1]);
+    result
+}
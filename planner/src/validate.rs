@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use crate::graph::{FieldGraph, LoadError, NodeId};
+use crate::locate::{line_column, Spanned};
+use crate::navgrid::NavGrid;
+
+/// Something wrong with a graph that would make paths through it fail or go wrong.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Problem {
+    /// The edge at this index in the edge list refers to a node that doesn't exist.
+    DanglingEdge { edge: usize, node: NodeId },
+    /// A tag lists a node that doesn't exist.
+    MissingTaggedNode { tag: String, node: NodeId },
+    /// A node's position isn't a finite number.
+    NonFiniteNode { node: NodeId },
+    /// A corner of the obstacle at this index isn't a finite number.
+    NonFiniteVertex { obstacle: usize, vertex: usize },
+    /// The edge goes from a node back to itself.
+    SelfLoop { edge: usize, node: NodeId },
+    /// The edge joins the same nodes as an earlier one, in the same direction.
+    DuplicateEdge { edge: usize, first: usize },
+    /// These nodes can't be reached from the rest of the graph, which is bigger.
+    Disconnected { nodes: Vec<NodeId> },
+    /// The node is somewhere a navgrid marks as blocked.
+    BlockedNode { node: NodeId },
+    /// The edge passes through somewhere a navgrid marks as blocked.
+    BlockedEdge { edge: usize },
+}
+
+impl Problem {
+    /// Whether the graph still works despite the problem, so that it can be loaded anyway.
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            Self::SelfLoop { .. } | Self::DuplicateEdge { .. } | Self::Disconnected { .. }
+        )
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DanglingEdge { edge, node } => {
+                write!(
+                    f,
+                    "edge {edge} refers to node {} which doesn't exist",
+                    node.0
+                )
+            }
+            Self::MissingTaggedNode { tag, node } => {
+                write!(f, "tag {tag:?} lists node {} which doesn't exist", node.0)
+            }
+            Self::NonFiniteNode { node } => {
+                write!(
+                    f,
+                    "node {} has a position that isn't a finite number",
+                    node.0
+                )
+            }
+            Self::NonFiniteVertex { obstacle, vertex } => write!(
+                f,
+                "corner {vertex} of obstacle {obstacle} isn't a finite number"
+            ),
+            Self::SelfLoop { edge, node } => {
+                write!(f, "edge {edge} connects node {} to itself", node.0)
+            }
+            Self::DuplicateEdge { edge, first } => write!(f, "edge {edge} duplicates edge {first}"),
+            Self::Disconnected { nodes } => {
+                if let [node] = nodes[..] {
+                    return write!(
+                        f,
+                        "node {} isn't connected to the rest of the graph",
+                        node.0
+                    );
+                }
+                let ids: Vec<_> = nodes.iter().map(|node| node.0.to_string()).collect();
+                write!(
+                    f,
+                    "nodes {} aren't connected to the rest of the graph",
+                    ids.join(", ")
+                )
+            }
+            Self::BlockedNode { node } => {
+                write!(f, "node {} is in a blocked cell of the navgrid", node.0)
+            }
+            Self::BlockedEdge { edge } => {
+                write!(f, "edge {edge} passes through blocked cells of the navgrid")
+            }
+        }
+    }
+}
+
+/// Where in a graph file something is.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Location {
+    /// Path to the value, such as `edges[3]`.
+    pub field: String,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {} ({})",
+            self.line, self.column, self.field
+        )
+    }
+}
+
+/// A problem found in a graph file, and where it is, if it can be pointed at.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FileProblem {
+    pub problem: Problem,
+    pub location: Option<Location>,
+}
+
+impl fmt::Display for FileProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{location}: {}", self.problem),
+            None => write!(f, "{}", self.problem),
+        }
+    }
+}
+
+impl FileProblem {
+    /// Finds where each of the problems with a graph is in the text of its file.
+    pub fn locate(json: &str, problems: Vec<Problem>) -> Vec<Self> {
+        let spanned = Spanned::parse(json);
+        problems
+            .into_iter()
+            .map(|problem| {
+                let location = spanned.as_ref().and_then(|file| {
+                    let (field, value) = find(file, &problem)?;
+                    let (line, column) = line_column(json, value.offset);
+                    Some(Location {
+                        field,
+                        line,
+                        column,
+                    })
+                });
+                Self { problem, location }
+            })
+            .collect()
+    }
+}
+
+impl FieldGraph {
+    /// Checks the graph for problems. If there are only warnings, it's safe to find paths through.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for (&node, pos) in &self.sg.nodes {
+            if !pos.is_finite() {
+                problems.push(Problem::NonFiniteNode { node });
+            }
+        }
+        // Earlier edges by the nodes they join, lowest ID first
+        let mut seen: HashMap<(NodeId, NodeId), Vec<usize>> = HashMap::new();
+        for (i, edge) in self.sg.edges.iter().enumerate() {
+            for node in [edge.start, edge.end] {
+                if !self.sg.nodes.contains_key(&node) {
+                    problems.push(Problem::DanglingEdge { edge: i, node });
+                }
+            }
+            if edge.start == edge.end {
+                problems.push(Problem::SelfLoop {
+                    edge: i,
+                    node: edge.start,
+                });
+                continue;
+            }
+            let key = (edge.start.min(edge.end), edge.start.max(edge.end));
+            let earlier = seen.entry(key).or_default();
+            // One-way edges in opposite directions are a two-way pair, not duplicates
+            let duplicate = earlier.iter().copied().find(|&j| {
+                let other = &self.sg.edges[j];
+                !edge.one_way || !other.one_way || other.start == edge.start
+            });
+            if let Some(first) = duplicate {
+                problems.push(Problem::DuplicateEdge { edge: i, first });
+            }
+            earlier.push(i);
+        }
+        for (name, tag) in &self.tags {
+            let mut missing: Vec<_> = tag
+                .nodes
+                .iter()
+                .filter(|node| !self.sg.nodes.contains_key(node))
+                .collect();
+            missing.sort();
+            problems.extend(missing.into_iter().map(|&node| Problem::MissingTaggedNode {
+                tag: name.clone(),
+                node,
+            }));
+        }
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            for (j, vertex) in obstacle.vertices.iter().enumerate() {
+                if !vertex.is_finite() {
+                    problems.push(Problem::NonFiniteVertex {
+                        obstacle: i,
+                        vertex: j,
+                    });
+                }
+            }
+        }
+        problems.extend(
+            self.small_components()
+                .into_iter()
+                .map(|nodes| Problem::Disconnected { nodes }),
+        );
+        problems
+    }
+
+    /// Finds nodes and edges where the navgrid says the robot can't drive, so that the graph
+    /// agrees with PathPlanner's pathfinder. The navgrid must be in the same frame as the graph.
+    pub fn check_navgrid(&self, navgrid: &NavGrid) -> Vec<Problem> {
+        let mut problems: Vec<_> = self
+            .sg
+            .nodes
+            .iter()
+            .filter(|(_, &pos)| navgrid.is_blocked(pos))
+            .map(|(&node, _)| Problem::BlockedNode { node })
+            .collect();
+        for (i, edge) in self.sg.edges.iter().enumerate() {
+            let (Some(&a), Some(&b)) =
+                (self.sg.nodes.get(&edge.start), self.sg.nodes.get(&edge.end))
+            else {
+                continue;
+            };
+            if navgrid.segment_blocked(a, b) {
+                problems.push(Problem::BlockedEdge { edge: i });
+            }
+        }
+        problems
+    }
+
+    // Groups of connected nodes other than the biggest one, ignoring edge directions
+    fn small_components(&self) -> Vec<Vec<NodeId>> {
+        // Union-find, keyed by node
+        let mut parent: BTreeMap<NodeId, NodeId> =
+            self.sg.nodes.keys().map(|&id| (id, id)).collect();
+        fn root(parent: &mut BTreeMap<NodeId, NodeId>, mut node: NodeId) -> NodeId {
+            while parent[&node] != node {
+                let grandparent = parent[&parent[&node]];
+                parent.insert(node, grandparent);
+                node = grandparent;
+            }
+            node
+        }
+        for edge in &self.sg.edges {
+            if parent.contains_key(&edge.start) && parent.contains_key(&edge.end) {
+                let a = root(&mut parent, edge.start);
+                let b = root(&mut parent, edge.end);
+                parent.insert(a.max(b), a.min(b));
+            }
+        }
+        let mut components: BTreeMap<NodeId, Vec<NodeId>> = BTreeMap::new();
+        for &node in self.sg.nodes.keys() {
+            let r = root(&mut parent, node);
+            components.entry(r).or_default().push(node);
+        }
+        let mut components: Vec<_> = components.into_values().collect();
+        // Keep the first of the biggest components, so that ties are reported the same way every time
+        let biggest = components
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, nodes)| (nodes.len(), std::cmp::Reverse(*i)))
+            .map(|(i, _)| i);
+        if let Some(biggest) = biggest {
+            components.remove(biggest);
+        }
+        components
+    }
+
+    /// Parses a graph in the JSON file format and checks it for problems, with where each one is
+    /// in the text.
+    pub fn check_json(json: &str) -> Result<(Self, Vec<FileProblem>), serde_json::Error> {
+        let graph = Self::from_json(json)?;
+        let problems = FileProblem::locate(json, graph.validate());
+        Ok((graph, problems))
+    }
+
+    /// Reads a graph file and checks it for problems.
+    pub fn load_checked(path: impl AsRef<Path>) -> Result<(Self, Vec<FileProblem>), LoadError> {
+        let json = std::fs::read_to_string(path).map_err(LoadError::Io)?;
+        Self::check_json(&json).map_err(LoadError::Parse)
+    }
+}
+
+// The value in the file that the problem is about, and the path to it
+fn find<'a>(file: &'a Spanned, problem: &Problem) -> Option<(String, &'a Spanned)> {
+    match problem {
+        Problem::DanglingEdge { edge, .. }
+        | Problem::SelfLoop { edge, .. }
+        | Problem::DuplicateEdge { edge, .. }
+        | Problem::BlockedEdge { edge } => {
+            let value = file.get("edges")?.elements().get(*edge)?;
+            Some((format!("edges[{edge}]"), value))
+        }
+        Problem::NonFiniteNode { node } | Problem::BlockedNode { node } => find_node(file, *node),
+        Problem::Disconnected { nodes } => find_node(file, *nodes.first()?),
+        Problem::MissingTaggedNode { tag, node } => {
+            let in_tag = file
+                .get("tags")
+                .and_then(|tags| tags.get(tag))
+                .and_then(|tag| tag.get("nodes"))
+                .map(|nodes| (format!("tags.{tag}.nodes"), nodes));
+            // Older files kept shoot nodes in their own list
+            let legacy = || {
+                ["shoot_idxs", "shoot_ids"]
+                    .into_iter()
+                    .find_map(|key| Some((key.to_owned(), file.get(key)?)))
+                    .filter(|_| tag == "shoot")
+            };
+            [in_tag, legacy()]
+                .into_iter()
+                .flatten()
+                .find_map(|(field, list)| {
+                    let (i, value) = list
+                        .elements()
+                        .iter()
+                        .enumerate()
+                        .find(|(_, value)| value.is_number(node.0))?;
+                    Some((format!("{field}[{i}]"), value))
+                })
+        }
+        Problem::NonFiniteVertex { obstacle, vertex } => {
+            let value = file
+                .get("obstacles")?
+                .elements()
+                .get(*obstacle)?
+                .elements()
+                .get(*vertex)?;
+            Some((format!("obstacles[{obstacle}][{vertex}]"), value))
+        }
+    }
+}
+
+fn find_node(file: &Spanned, node: NodeId) -> Option<(String, &Spanned)> {
+    let (i, value) =
+        file.get("nodes")?
+            .elements()
+            .iter()
+            .enumerate()
+            .find(|(i, value)| match value.get("id") {
+                Some(id) => id.is_number(node.0),
+                // Older files refer to nodes by their index in the list
+                None => *i == node.0 as usize,
+            })?;
+    Some((format!("nodes[{i}]"), value))
+}
@@ -0,0 +1,66 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use glam::Vec2;
+
+use crate::obstacle::{segment_blocked, Obstacle};
+
+/// Distance a robot's center has to keep from obstacles: the radius of its bounding circle,
+/// so that it can turn anywhere along the way.
+pub fn clearance(bumper_size: f32) -> f32 {
+    bumper_size * FRAC_1_SQRT_2
+}
+
+// Nodes are placed slightly further out than the clearance, so that edges running along
+// an inflated obstacle's side aren't considered to touch it.
+const NODE_MARGIN: f32 = 0.01;
+
+/// Roadmap of straight collision-free paths between the corners of obstacles.
+/// It contains the shortest route between any of its nodes that avoids the obstacles.
+pub struct VisibilityGraph {
+    pub nodes: Vec<Vec2>,
+    pub edges: Vec<(usize, usize)>,
+    // Obstacles grown by the clearance, which the robot's center must stay out of
+    inflated: Vec<Obstacle>,
+}
+
+impl VisibilityGraph {
+    pub fn new(obstacles: &[Obstacle], clearance: f32) -> Self {
+        let inflated: Vec<_> = obstacles
+            .iter()
+            .filter(|obstacle| obstacle.vertices.len() >= 3)
+            .map(|obstacle| obstacle.inflated(clearance).0)
+            .collect();
+
+        let mut graph = Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            inflated,
+        };
+        graph.nodes = obstacles
+            .iter()
+            .filter(|obstacle| obstacle.vertices.len() >= 3)
+            .flat_map(|obstacle| {
+                let (corners, convex) = obstacle.inflated(clearance + NODE_MARGIN);
+                corners.vertices.into_iter().zip(convex)
+            })
+            .filter(|&(_, convex)| convex)
+            .map(|(corner, _)| corner)
+            // Corners that end up inside another obstacle can't be reached
+            .filter(|&corner| !graph.inflated.iter().any(|o| o.contains(corner)))
+            .collect();
+
+        for i in 0..graph.nodes.len() {
+            for j in i + 1..graph.nodes.len() {
+                if graph.visible(graph.nodes[i], graph.nodes[j]) {
+                    graph.edges.push((i, j));
+                }
+            }
+        }
+        graph
+    }
+
+    /// Whether the robot can drive straight from `a` to `b` without hitting an obstacle.
+    pub fn visible(&self, a: Vec2, b: Vec2) -> bool {
+        !segment_blocked(&self.inflated, a, b)
+    }
+}
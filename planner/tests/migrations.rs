@@ -0,0 +1,106 @@
+//! Graph files written in every past version of the format still load, and saving upgrades them.
+
+use robot_planner::{Color, Edge, FieldGraph, NodeId, Problem, Vec2, CURRENT_VERSION};
+
+fn ids(ids: &[u32]) -> Vec<NodeId> {
+    ids.iter().copied().map(NodeId).collect()
+}
+
+fn tagged(graph: &FieldGraph, tag: &str) -> Vec<NodeId> {
+    let mut nodes: Vec<_> = graph.tags[tag].nodes.iter().copied().collect();
+    nodes.sort();
+    nodes
+}
+
+// Saving and loading again gives the same graph, in the current version
+fn assert_round_trips(graph: &FieldGraph) {
+    let json = graph.to_json();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["version"], CURRENT_VERSION);
+    let reloaded = FieldGraph::from_json(&json).unwrap();
+    assert_eq!(reloaded.sg.nodes, graph.sg.nodes);
+    assert_eq!(reloaded.sg.edges, graph.sg.edges);
+    assert_eq!(reloaded.obstacles, graph.obstacles);
+    for tag in graph.tags.keys() {
+        assert_eq!(reloaded.tags[tag].color, graph.tags[tag].color);
+        assert_eq!(tagged(&reloaded, tag), tagged(graph, tag));
+    }
+}
+
+#[test]
+fn version_0_nodes_by_index() {
+    let mut graph = FieldGraph::from_json(include_str!("fixtures/v0.json")).unwrap();
+    assert_eq!(
+        graph.sg.nodes.keys().copied().collect::<Vec<_>>(),
+        ids(&[0, 1, 2, 3])
+    );
+    assert_eq!(graph.sg.nodes[&NodeId(2)], Vec2::new(2.0, 3.5));
+    assert_eq!(graph.sg.edges[1], Edge::new(NodeId(1), NodeId(2)));
+    assert_eq!(tagged(&graph, "shoot"), ids(&[2]));
+    assert_eq!(graph.tags["shoot"].color, Color::rgb(255, 165, 0));
+    assert!(graph.obstacles.is_empty());
+    // New nodes don't reuse the IDs of existing ones
+    assert_eq!(graph.sg.add_node(Vec2::ZERO), NodeId(4));
+    assert_round_trips(&graph);
+}
+
+#[test]
+fn version_1_node_ids_and_edge_attributes() {
+    let mut graph = FieldGraph::from_json(include_str!("fixtures/v1.json")).unwrap();
+    assert_eq!(
+        graph.sg.nodes.keys().copied().collect::<Vec<_>>(),
+        ids(&[0, 2, 3, 5])
+    );
+    let edges = &graph.sg.edges;
+    assert_eq!(edges[0], Edge::new(NodeId(0), NodeId(5)));
+    assert!(edges[1].one_way);
+    assert_eq!((edges[1].start, edges[1].end), (NodeId(5), NodeId(2)));
+    assert_eq!(edges[2].cost_multiplier, 2.5);
+    assert_eq!(edges[2].max_speed, Some(1.5));
+    assert_eq!(tagged(&graph, "shoot"), ids(&[2, 3]));
+    // IDs of deleted nodes stay used
+    assert_eq!(graph.sg.add_node(Vec2::ZERO), NodeId(7));
+    assert_round_trips(&graph);
+}
+
+#[test]
+fn version_2_tags_and_obstacles() {
+    let graph = FieldGraph::from_json(include_str!("fixtures/v2.json")).unwrap();
+    assert_eq!(tagged(&graph, "shoot"), ids(&[2]));
+    assert_eq!(graph.tags["shoot"].color, Color::rgb(255, 0, 0));
+    assert_eq!(tagged(&graph, "trap"), ids(&[1]));
+    assert_eq!(
+        graph.tags["trap"].color,
+        Color {
+            r: 0x12,
+            g: 0xAB,
+            b: 0x34,
+            a: 0x80
+        }
+    );
+    // Tags every graph has are added
+    assert!(graph.tags["amp"].nodes.is_empty());
+    assert_eq!(graph.obstacles.len(), 1);
+    assert_eq!(graph.obstacles[0].vertices[2], Vec2::new(1.5, 2.5));
+    assert_eq!(graph.sg.edges[1].max_speed, Some(2.0));
+    assert_round_trips(&graph);
+}
+
+#[test]
+fn default_graph_is_version_0() {
+    let graph = FieldGraph::from_json(include_str!("../../assets/default-graph.json")).unwrap();
+    assert_eq!(graph.sg.nodes.len(), 14);
+    // It has self-loops, but those are only warnings
+    assert!(graph.validate().iter().all(Problem::is_warning));
+    assert_round_trips(&graph);
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let json = format!(
+        r#"{{"version": {}, "nodes": [], "edges": []}}"#,
+        CURRENT_VERSION + 1
+    );
+    let e = FieldGraph::from_json(&json).unwrap_err();
+    assert!(e.to_string().contains("only versions up to"), "{e}");
+}
@@ -0,0 +1,81 @@
+//! Exported paths are valid PathPlanner files that go through the waypoints.
+
+use robot_planner::{PathConstraints, PathPlannerPath, TooFewWaypoints, Vec2, Waypoint};
+use serde_json::Value;
+
+fn waypoint(x: f32, y: f32, max_speed: Option<f32>) -> Waypoint {
+    Waypoint {
+        pos: Vec2::new(x, y),
+        max_speed,
+    }
+}
+
+fn point(value: &Value) -> Vec2 {
+    Vec2::new(
+        value["x"].as_f64().unwrap() as f32,
+        value["y"].as_f64().unwrap() as f32,
+    )
+}
+
+fn export(waypoints: Vec<Waypoint>) -> Value {
+    let path = PathPlannerPath::new(waypoints, PathConstraints::default()).unwrap();
+    serde_json::from_str(&path.to_json()).unwrap()
+}
+
+#[test]
+fn two_waypoints() {
+    let file = export(vec![waypoint(1.0, 1.0, None), waypoint(4.0, 1.0, None)]);
+    let waypoints = file["waypoints"].as_array().unwrap();
+    assert_eq!(waypoints.len(), 2);
+    assert_eq!(point(&waypoints[0]["anchor"]), Vec2::new(1.0, 1.0));
+    assert_eq!(point(&waypoints[1]["anchor"]), Vec2::new(4.0, 1.0));
+    // A straight line, with controls a third of the way from each end
+    assert!(waypoints[0]["prevControl"].is_null());
+    assert_eq!(point(&waypoints[0]["nextControl"]), Vec2::new(2.0, 1.0));
+    assert_eq!(point(&waypoints[1]["prevControl"]), Vec2::new(3.0, 1.0));
+    assert!(waypoints[1]["nextControl"].is_null());
+    assert!(file["rotationTargets"].as_array().unwrap().is_empty());
+    assert!(file["constraintZones"].as_array().unwrap().is_empty());
+    assert_eq!(file["goalEndState"]["rotation"], 0.0);
+    assert_eq!(file["idealStartingState"]["rotation"], 0.0);
+    assert_eq!(file["globalConstraints"]["maxVelocity"], 3.0);
+}
+
+#[test]
+fn three_waypoints() {
+    let file = export(vec![
+        waypoint(0.0, 0.0, None),
+        waypoint(2.0, 0.0, Some(1.5)),
+        waypoint(2.0, 2.0, None),
+    ]);
+    let waypoints = file["waypoints"].as_array().unwrap();
+    assert_eq!(waypoints.len(), 3);
+    assert_eq!(point(&waypoints[1]["anchor"]), Vec2::new(2.0, 0.0));
+    // The corner's controls line up, so the curve passes through it smoothly
+    let corner = point(&waypoints[1]["anchor"]);
+    let before = corner - point(&waypoints[1]["prevControl"]);
+    let after = point(&waypoints[1]["nextControl"]) - corner;
+    assert!(before.normalize().abs_diff_eq(after.normalize(), 1e-5));
+    // The robot faces the end from the corner on
+    let targets = file["rotationTargets"].as_array().unwrap();
+    assert_eq!(targets.len(), 1);
+    assert_eq!(targets[0]["waypointRelativePos"], 1.0);
+    assert_eq!(targets[0]["rotationDegrees"], 90.0);
+    assert_eq!(file["goalEndState"]["rotation"], 90.0);
+    assert_eq!(file["idealStartingState"]["rotation"], 45.0);
+    // The speed limit on the way to the corner
+    let zones = file["constraintZones"].as_array().unwrap();
+    assert_eq!(zones.len(), 1);
+    assert_eq!(zones[0]["minWaypointRelativePos"], 0.0);
+    assert_eq!(zones[0]["maxWaypointRelativePos"], 1.0);
+    assert_eq!(zones[0]["constraints"]["maxVelocity"], 1.5);
+}
+
+#[test]
+fn too_few_waypoints() {
+    for waypoints in [vec![], vec![waypoint(1.0, 1.0, None)]] {
+        let n = waypoints.len();
+        let e = PathPlannerPath::new(waypoints, PathConstraints::default()).unwrap_err();
+        assert_eq!(e, TooFewWaypoints(n));
+    }
+}
@@ -0,0 +1,88 @@
+//! Paths keep the robot's clearance from obstacles, not just its center, and smoothing them
+//! respects edges that steer paths on purpose.
+
+use robot_planner::{smooth_path, FieldGraph, Vec2, Waypoint};
+
+// A wall just below the straight line between the ends, and a node well above it to go around by
+const GRAPH: &str = r#"{
+  "version": 2,
+  "nodes": [{"id": 0, "pos": [1.5, 3.0]}],
+  "edges": [],
+  "next_id": 1,
+  "obstacles": [
+    [[1.0, -1.0], [2.0, -1.0], [2.0, 0.0], [1.0, 0.0]]
+  ]
+}"#;
+
+#[test]
+fn straight_when_the_center_fits() {
+    let graph = FieldGraph::from_json(GRAPH).unwrap();
+    let (start, end) = (Vec2::new(0.0, 0.1), Vec2::new(3.0, 0.1));
+    let path = graph.find_path(start, end, 0.0, 4.0).unwrap();
+    let points: Vec<_> = path.iter().map(|wp| wp.pos).collect();
+    assert_eq!(points, [start, end]);
+}
+
+#[test]
+fn around_when_the_robot_doesnt_fit() {
+    let graph = FieldGraph::from_json(GRAPH).unwrap();
+    let (start, end) = (Vec2::new(0.0, 0.1), Vec2::new(3.0, 0.1));
+    let path = graph.find_path(start, end, 0.5, 4.0).unwrap();
+    let points: Vec<_> = path.iter().map(|wp| wp.pos).collect();
+    assert_eq!(points, [start, Vec2::new(1.5, 3.0), end]);
+}
+
+// Smooths the path through the corner at (1, 1), given the edges of a graph around it
+fn smooth_corner(edges: &str) -> Vec<Vec2> {
+    let json = format!(
+        r#"{{
+  "version": 2,
+  "nodes": [
+    {{"id": 0, "pos": [0.0, 0.0]}},
+    {{"id": 1, "pos": [1.0, 1.0]}},
+    {{"id": 2, "pos": [2.0, 0.0]}},
+    {{"id": 3, "pos": [1.0, -1.0]}},
+    {{"id": 4, "pos": [1.0, 0.5]}}
+  ],
+  "edges": {edges},
+  "next_id": 5
+}}"#
+    );
+    let graph = FieldGraph::from_json(&json).unwrap();
+    let path: Vec<_> = [0.0, 1.0, 2.0]
+        .into_iter()
+        .zip([0.0, 1.0, 0.0])
+        .map(|(x, y)| Waypoint {
+            pos: Vec2::new(x, y),
+            max_speed: None,
+        })
+        .collect();
+    smooth_path(&path, &graph.sg, &[], 0.0)
+        .iter()
+        .map(|wp| wp.pos)
+        .collect()
+}
+
+#[test]
+fn smoothing_cuts_plain_corners() {
+    let points = smooth_corner("[[0, 1], [1, 2], [3, 4]]");
+    assert_eq!(points, [Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0)]);
+}
+
+#[test]
+fn smoothing_keeps_costly_and_one_way_edges() {
+    for edges in [
+        r#"[{"nodes": [0, 1], "cost_multiplier": 2.0}, [1, 2]]"#,
+        r#"[[0, 1], {"nodes": [1, 2], "one_way": true}]"#,
+    ] {
+        // Still goes up to the corner, though it's rounded off
+        let points = smooth_corner(edges);
+        assert!(points.iter().any(|p| p.y > 0.5), "{edges}: {points:?}");
+    }
+}
+
+#[test]
+fn smoothing_doesnt_cut_across_one_way_edges() {
+    let points = smooth_corner(r#"[[0, 1], [1, 2], {"nodes": [3, 4], "one_way": true}]"#);
+    assert!(points.iter().any(|p| p.y > 0.5), "{points:?}");
+}
@@ -0,0 +1,113 @@
+//! Each kind of problem is found, classed as an error or a warning, and pointed at in the file.
+
+use robot_planner::{FieldGraph, NodeId, Problem};
+
+// Problems found in the file, with the field and line each one is at
+fn check(json: &str) -> Vec<(Problem, String, usize)> {
+    let (_, problems) = FieldGraph::check_json(json).unwrap();
+    problems
+        .into_iter()
+        .map(|p| {
+            let location = p.location.expect("problem should have a location");
+            (p.problem, location.field, location.line)
+        })
+        .collect()
+}
+
+#[test]
+fn dangling_edge() {
+    let problems = check(include_str!("fixtures/validate/dangling-edge.json"));
+    let problem = Problem::DanglingEdge {
+        edge: 1,
+        node: NodeId(5),
+    };
+    assert!(!problem.is_warning());
+    assert_eq!(problems, [(problem, "edges[1]".to_owned(), 9)]);
+}
+
+#[test]
+fn self_loop() {
+    let problems = check(include_str!("fixtures/validate/self-loop.json"));
+    let problem = Problem::SelfLoop {
+        edge: 1,
+        node: NodeId(1),
+    };
+    assert!(problem.is_warning());
+    assert_eq!(problems, [(problem, "edges[1]".to_owned(), 9)]);
+}
+
+#[test]
+fn duplicate_edge() {
+    // One-way edges in opposite directions aren't duplicates, but a third edge is
+    let problems = check(include_str!("fixtures/validate/duplicate-edge.json"));
+    let problem = Problem::DuplicateEdge { edge: 2, first: 0 };
+    assert!(problem.is_warning());
+    assert_eq!(problems, [(problem, "edges[2]".to_owned(), 10)]);
+}
+
+#[test]
+fn missing_tagged_node() {
+    let problems = check(include_str!("fixtures/validate/missing-tagged-node.json"));
+    let problem = Problem::MissingTaggedNode {
+        tag: "intake".to_owned(),
+        node: NodeId(3),
+    };
+    assert!(!problem.is_warning());
+    assert_eq!(problems, [(problem, "tags.intake.nodes[1]".to_owned(), 12)]);
+}
+
+#[test]
+fn missing_shoot_id_in_older_file() {
+    let problems = check(include_str!("fixtures/validate/missing-shoot-id.json"));
+    let problem = Problem::MissingTaggedNode {
+        tag: "shoot".to_owned(),
+        node: NodeId(3),
+    };
+    assert!(!problem.is_warning());
+    assert_eq!(problems, [(problem, "shoot_ids[1]".to_owned(), 10)]);
+}
+
+#[test]
+fn disconnected() {
+    let problems = check(include_str!("fixtures/validate/disconnected.json"));
+    let problem = Problem::Disconnected {
+        nodes: vec![NodeId(3), NodeId(4)],
+    };
+    assert!(problem.is_warning());
+    assert_eq!(problems, [(problem, "nodes[3]".to_owned(), 7)]);
+}
+
+#[test]
+fn non_finite_coordinates() {
+    // Too big for an f32, so they load as infinity
+    let problems = check(include_str!("fixtures/validate/non-finite.json"));
+    let node = Problem::NonFiniteNode { node: NodeId(1) };
+    let vertex = Problem::NonFiniteVertex {
+        obstacle: 0,
+        vertex: 2,
+    };
+    assert!(!node.is_warning());
+    assert!(!vertex.is_warning());
+    assert_eq!(
+        problems,
+        [
+            (node, "nodes[1]".to_owned(), 5),
+            (vertex, "obstacles[0][2]".to_owned(), 12),
+        ]
+    );
+}
+
+#[test]
+fn columns_are_counted_in_characters() {
+    let json = r##"{"version": 2, "nodes": [{"id": 0, "pos": [0, 0]}], "edges": [],
+"tags": {"café": {"color": "#FFFFFF", "nodes": [0, 7]}}}"##;
+    let (_, problems) = FieldGraph::check_json(json).unwrap();
+    let location = problems[0].location.as_ref().unwrap();
+    assert_eq!(location.field, "tags.café.nodes[1]");
+    // The accented letter is two bytes, but one column
+    let column = r##""tags": {"café": {"color": "#FFFFFF", "nodes": [0, "##
+        .chars()
+        .count()
+        + 1;
+    assert_eq!((location.line, location.column), (2, column));
+}
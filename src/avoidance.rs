@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use robot_planner::Waypoint;
+
+use crate::trajectory::{Trajectory, TrajectoryConstraints};
+
+// Time between the points where trajectories are checked against each other, in s
+const CHECK_STEP: f32 = 0.05;
+// A yielding robot tries waiting this long, in s, then longer by as much again each time
+const WAIT_STEP: f32 = 0.25;
+const MAX_WAIT_STEPS: usize = 40;
+
+/// The stretch of time and space a higher-priority robot has claimed for its trajectory.
+pub struct Reservation {
+    /// Number of the robot that holds the reservation.
+    pub robot: usize,
+    /// Radius of the robot's bounding circle, in m.
+    pub clearance: f32,
+    pub trajectory: Trajectory,
+    /// How far into its trajectory the robot already is, in s.
+    pub elapsed: f32,
+}
+
+/// Where and how long a robot waits to let another one through.
+#[derive(Clone, Copy, Debug)]
+pub struct Yield {
+    /// Number of the robot being let through.
+    pub to: usize,
+    pub pos: Vec2,
+    /// s
+    pub wait: f32,
+    /// Time along the yielding robot's trajectory when it sets off again, in s.
+    pub until: f32,
+}
+
+/// How a trajectory was fitted around the reservations.
+#[derive(Clone, Copy, Debug)]
+pub enum Avoidance {
+    /// Nothing was in the way.
+    Clear,
+    Yielded(Yield),
+    /// No amount of waiting at any waypoint avoids the given robot.
+    Unresolved {
+        with: usize,
+    },
+}
+
+/// The first reserved robot the trajectory comes too close to, and when.
+/// Robots are too close once their bounding circles overlap. Robots that are already too close at the
+/// start are ignored, since there's no avoiding them.
+fn first_conflict(
+    trajectory: &Trajectory,
+    clearance: f32,
+    reservations: &[Reservation],
+) -> Option<(usize, f32)> {
+    reservations
+        .iter()
+        .filter_map(|r| {
+            let safe_distance = clearance + r.clearance;
+            let other = |t: f32| r.trajectory.sample(r.elapsed + t).pos;
+            let dist = |t: f32| (trajectory.sample(t).pos - other(t)).length();
+            if dist(0.0) < safe_distance {
+                return None;
+            }
+            let end = trajectory
+                .total_time()
+                .max(r.trajectory.total_time() - r.elapsed);
+            let steps = (end / CHECK_STEP).ceil() as usize;
+            (0..=steps)
+                .map(|i| i as f32 * CHECK_STEP)
+                .find(|&t| dist(t) < safe_distance)
+                .map(|t| (r.robot, t))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Time-parameterizes the path so that it stays clear of the reserved trajectories.
+///
+/// This is prioritized planning: the robot keeps its route, and if it would run into a robot with
+/// a reservation, it stops at a waypoint before the collision and waits for the other robot to pass.
+/// Waypoints closest to the collision are tried first, along with the shortest waits.
+pub fn schedule(
+    path: &[Waypoint],
+    constraints: &TrajectoryConstraints,
+    initial_speed: f32,
+    initial_heading: f32,
+    clearance: f32,
+    reservations: &[Reservation],
+) -> (Trajectory, Avoidance) {
+    let direct = Trajectory::generate(path, constraints, initial_speed, initial_heading);
+    let Some((with, _)) = first_conflict(&direct, clearance, reservations) else {
+        return (direct, Avoidance::Clear);
+    };
+
+    for k in (0..path.len().saturating_sub(1)).rev() {
+        let approach =
+            Trajectory::generate(&path[..=k], constraints, initial_speed, initial_heading);
+        // Waiting here is no use if the robot can't get here in the first place
+        if first_conflict(&approach, clearance, reservations)
+            .is_some_and(|(_, t)| t <= approach.total_time())
+        {
+            continue;
+        }
+        let departure =
+            Trajectory::generate(&path[k..], constraints, 0.0, approach.final_heading());
+        for step in 1..=MAX_WAIT_STEPS {
+            let wait = step as f32 * WAIT_STEP;
+            let trajectory = approach.clone().then(wait, departure.clone());
+            if first_conflict(&trajectory, clearance, reservations).is_none() {
+                let yielded = Yield {
+                    to: with,
+                    pos: path[k].pos,
+                    wait,
+                    until: approach.total_time() + wait,
+                };
+                return (trajectory, Avoidance::Yielded(yielded));
+            }
+        }
+    }
+    (direct, Avoidance::Unresolved { with })
+}
@@ -0,0 +1,336 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use robot_planner::{
+    clearance, path_length, FieldGraph, FileProblem, NavGrid, PathPlannerPath, Vec2,
+};
+use serde::Serialize;
+
+use crate::profile::{Alliance, RobotProfile, DEFAULT_BUMPER_SIZE};
+
+/// FRC robot pathfinding simulation, and tools for the graph files it uses.
+/// Without a command, the sim is launched as with `gui`.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub gui: GuiArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Launch the sim in a window.
+    Gui(GuiArgs),
+    /// Run a scenario without a window and print how each robot did as JSON.
+    Run {
+        /// Where each robot is sent, and how it drives there.
+        scenario: String,
+        /// Graph the robots pathfind along.
+        #[arg(default_value = "graph.json")]
+        graph: String,
+        /// Profile of the next robot in the alliance, starting from robot 1.
+        #[arg(long = "robot", value_name = "PROFILE")]
+        robots: Vec<String>,
+    },
+    /// Find the fastest path between two field positions and print it as JSON.
+    Path {
+        /// Graph to pathfind along.
+        graph: String,
+        /// Start position, as `x,y` in field coordinates.
+        #[arg(allow_hyphen_values = true, value_parser = parse_point)]
+        from: Vec2,
+        /// End position, as `x,y` in field coordinates.
+        #[arg(allow_hyphen_values = true, value_parser = parse_point)]
+        to: Vec2,
+        /// Top speed of the robot, in m/s.
+        #[arg(long, default_value_t = 4.0)]
+        max_speed: f32,
+        /// Side length of the robot's square bumpers, in m, which sets how far it keeps from
+        /// obstacles.
+        #[arg(long, default_value_t = DEFAULT_BUMPER_SIZE)]
+        bumper_size: f32,
+    },
+    /// Export paths as PathPlanner `.path` files, from a start position through each stop in turn.
+    /// With several stops, each leg is its own path, and they're linked where they meet.
+    Export {
+        /// Graph to pathfind along.
+        graph: String,
+        /// Start position, as `x,y` in field coordinates.
+        #[arg(allow_hyphen_values = true, value_parser = parse_point)]
+        from: Vec2,
+        /// Where to go next: a position as `x,y`, or the name of a tag to go to the closest node
+        /// with. Can be given several times.
+        #[arg(
+            long = "to",
+            value_name = "STOP",
+            required = true,
+            allow_hyphen_values = true,
+            value_parser = parse_stop
+        )]
+        stops: Vec<Stop>,
+        /// Name of the path, which legs are numbered after.
+        #[arg(long, default_value = "Path")]
+        name: String,
+        /// Folder to write the paths to, such as `deploy/pathplanner/paths` in robot code.
+        #[arg(long, default_value = ".")]
+        out: PathBuf,
+        /// Profile to take the robot's speed and acceleration limits from.
+        #[arg(long, value_name = "PROFILE")]
+        robot: Option<PathBuf>,
+    },
+    /// Check a graph file for problems, saying where in the file each one is. Fails if there
+    /// are any that would make paths through the graph fail.
+    Validate {
+        /// Graph to check.
+        graph: String,
+        /// PathPlanner `navgrid.json` that nodes and edges must stay out of the blocked cells of.
+        #[arg(long, value_name = "NAVGRID")]
+        navgrid: Option<String>,
+    },
+    /// Read a graph file in any supported format and write it in another.
+    Convert {
+        /// Graph to read.
+        input: String,
+        /// Where to write it, replacing the file if it exists.
+        output: String,
+        /// Format to write.
+        #[arg(long, value_enum, default_value_t = Format::Graph)]
+        to: Format,
+    },
+}
+
+#[derive(Args, Clone)]
+pub struct GuiArgs {
+    /// Graph to show and edit, which is also where it's saved.
+    #[arg(default_value = "graph.json")]
+    pub graph: String,
+    /// Field image, relative to `assets/`. Defaults to the alliance's half of the 2025 field.
+    #[arg(long, value_name = "IMAGE")]
+    pub field: Option<String>,
+    /// PathPlanner `navgrid.json` whose blocked areas can be added as obstacles.
+    #[arg(long, value_name = "NAVGRID")]
+    pub navgrid: Option<String>,
+    #[arg(long, value_enum, default_value_t)]
+    pub alliance: Alliance,
+    /// Profile of the next robot in the alliance, starting from robot 1.
+    #[arg(long = "robot", value_name = "PROFILE")]
+    pub robots: Vec<String>,
+}
+
+/// Graph file formats that `convert` can write. Any of them can be read.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// The current graph file format.
+    Graph,
+    /// Version 0 of the graph file format, which only keeps nodes, plain edges and shoot tags.
+    Legacy,
+}
+
+/// Somewhere an exported path goes.
+#[derive(Clone)]
+pub enum Stop {
+    Point(Vec2),
+    /// The closest node with the tag.
+    Tag(String),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Point(p) => write!(f, "{p}"),
+            Self::Tag(tag) => write!(f, "tag {tag:?}"),
+        }
+    }
+}
+
+fn parse_stop(s: &str) -> Result<Stop, String> {
+    if s.contains(',') {
+        parse_point(s).map(Stop::Point)
+    } else {
+        Ok(Stop::Tag(s.to_owned()))
+    }
+}
+
+fn parse_point(s: &str) -> Result<Vec2, String> {
+    let (x, y) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected x,y but got {s:?}"))?;
+    let coord = |c: &str| {
+        c.trim()
+            .parse::<f32>()
+            .map_err(|e| format!("invalid coordinate {c:?}: {e}"))
+    };
+    Ok(Vec2::new(coord(x)?, coord(y)?))
+}
+
+/// Loads a graph file and makes sure paths can be found through it.
+fn load_valid_graph(path: &str) -> Result<FieldGraph, String> {
+    let graph = FieldGraph::load(path).map_err(|e| format!("Couldn't load graph {path}: {e}"))?;
+    let errors = graph
+        .validate()
+        .into_iter()
+        .filter(|problem| !problem.is_warning())
+        .count();
+    if errors > 0 {
+        return Err(format!(
+            "Invalid graph {path}: it has {errors} errors (run `validate` to list them)"
+        ));
+    }
+    Ok(graph)
+}
+
+#[derive(Serialize)]
+struct PathOutput {
+    /// m
+    length: f32,
+    waypoints: Vec<WaypointOutput>,
+}
+
+#[derive(Serialize)]
+struct WaypointOutput {
+    pos: [f32; 2],
+    /// m/s, on the way to this waypoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_speed: Option<f32>,
+}
+
+pub fn path(
+    graph_path: &str,
+    from: Vec2,
+    to: Vec2,
+    max_speed: f32,
+    bumper_size: f32,
+) -> Result<(), String> {
+    let graph = load_valid_graph(graph_path)?;
+    let path = graph
+        .find_path(from, to, clearance(bumper_size), max_speed)
+        .ok_or_else(|| format!("No path from {from} to {to}"))?;
+    let output = PathOutput {
+        length: path_length(&path),
+        waypoints: path
+            .iter()
+            .map(|wp| WaypointOutput {
+                pos: wp.pos.to_array(),
+                max_speed: wp.max_speed,
+            })
+            .collect(),
+    };
+    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    Ok(())
+}
+
+pub fn validate(graph_path: &str, navgrid_path: Option<&str>) -> Result<(), String> {
+    let json = std::fs::read_to_string(graph_path)
+        .map_err(|e| format!("Couldn't load graph {graph_path}: {e}"))?;
+    let (graph, mut problems) = FieldGraph::check_json(&json)
+        .map_err(|e| format!("Couldn't load graph {graph_path}: {e}"))?;
+    if let Some(navgrid_path) = navgrid_path {
+        let navgrid = NavGrid::load(navgrid_path)
+            .map_err(|e| format!("Couldn't load navgrid {navgrid_path}: {e}"))?;
+        problems.extend(FileProblem::locate(&json, graph.check_navgrid(&navgrid)));
+    }
+    for problem in &problems {
+        let severity = if problem.problem.is_warning() {
+            "warning"
+        } else {
+            "error"
+        };
+        println!("{graph_path}: {severity}: {problem}");
+    }
+    let errors = problems
+        .iter()
+        .filter(|problem| !problem.problem.is_warning())
+        .count();
+    if errors > 0 {
+        return Err(format!("{graph_path} has {errors} errors"));
+    }
+    match problems.len() {
+        0 => eprintln!("{graph_path} is valid"),
+        warnings => eprintln!("{graph_path} is valid, with {warnings} warnings"),
+    }
+    Ok(())
+}
+
+pub fn convert(input: &str, output: &str, format: Format) -> Result<(), String> {
+    let graph = FieldGraph::load(input).map_err(|e| format!("Couldn't load graph {input}: {e}"))?;
+    let json = match format {
+        Format::Graph => graph.to_json(),
+        Format::Legacy => {
+            let dropped_edges = graph
+                .sg
+                .edges
+                .iter()
+                .filter(|edge| !edge.has_default_attrs())
+                .count();
+            let dropped_tags = graph
+                .tags
+                .iter()
+                .filter(|(name, tag)| *name != "shoot" && !tag.nodes.is_empty())
+                .count();
+            if dropped_edges > 0 || dropped_tags > 0 || !graph.obstacles.is_empty() {
+                eprintln!(
+                    "Legacy format leaves out the attributes of {dropped_edges} edges, \
+                     {dropped_tags} tags and {} obstacles",
+                    graph.obstacles.len()
+                );
+            }
+            graph.to_legacy_json()
+        }
+    };
+    std::fs::write(output, json).map_err(|e| format!("Couldn't write {output}: {e}"))?;
+    eprintln!("Converted {input} to {output}");
+    Ok(())
+}
+
+pub fn export(
+    graph_path: &str,
+    from: Vec2,
+    stops: &[Stop],
+    name: &str,
+    out: &Path,
+    robot: Option<&Path>,
+) -> Result<(), String> {
+    let graph = load_valid_graph(graph_path)?;
+    let default = RobotProfile::default_for(Alliance::Blue, 0);
+    let profile = match robot {
+        Some(path) => RobotProfile::load(path, &default)?,
+        None => default,
+    };
+    let constraints = profile.path_constraints();
+    let clearance = clearance(profile.bumper_size);
+
+    let mut start = from;
+    let mut start_link = None;
+    for (i, stop) in stops.iter().enumerate() {
+        let waypoints = match stop {
+            Stop::Point(end) => graph.find_path(start, *end, clearance, profile.drive.max_speed),
+            Stop::Tag(tag) if graph.tags.contains_key(tag) => {
+                graph.find_path_to_tag(start, tag, clearance, profile.drive.max_speed)
+            }
+            Stop::Tag(tag) => return Err(format!("No tag named {tag:?} in {graph_path}")),
+        }
+        .ok_or_else(|| format!("No path from {start} to {stop}"))?;
+        start = waypoints.last().unwrap().pos;
+
+        let (file_name, end_link) = if stops.len() == 1 {
+            (format!("{name}.path"), None)
+        } else {
+            let end_link = (i + 1 < stops.len()).then(|| format!("{name} stop {}", i + 1));
+            (format!("{name} {}.path", i + 1), end_link)
+        };
+        let mut path = PathPlannerPath::new(waypoints, constraints)
+            .map_err(|e| format!("Couldn't export the path to {stop}: {e}"))?;
+        path.start_link = start_link;
+        path.end_link = end_link.clone();
+        let file = out.join(file_name);
+        path.save(&file)
+            .map_err(|e| format!("Couldn't write {}: {e}", file.display()))?;
+        eprintln!("Exported {}", file.display());
+        start_link = end_link;
+    }
+    Ok(())
+}
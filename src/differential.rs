@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+
+use crate::physics::{AngularVelocity, PhysicsSet, Velocity};
+use crate::profile::DifferentialProfile;
+
+pub struct DifferentialPlugin;
+impl Plugin for DifferentialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            drive_differential.in_set(PhysicsSet::Drivetrain),
+        );
+    }
+}
+
+/// Models a differential drivetrain. The robot's [`Velocity`] and [`AngularVelocity`] are taken as the
+/// chassis speeds it's commanded to reach, and replaced with the ones its wheels achieve. Any sideways
+/// part of the commanded velocity is dropped, since the robot can only drive along its heading.
+#[derive(Component)]
+pub struct DifferentialDrive {
+    pub profile: DifferentialProfile,
+    /// m/s
+    pub left_speed: f32,
+    /// m/s
+    pub right_speed: f32,
+}
+
+impl DifferentialDrive {
+    pub fn new(profile: DifferentialProfile) -> Self {
+        Self {
+            profile,
+            left_speed: 0.0,
+            right_speed: 0.0,
+        }
+    }
+
+    /// Moves the wheel speeds toward the ones that give the commanded forward and angular speeds,
+    /// within their limits, and returns the speeds they actually give.
+    fn update(&mut self, forward: f32, angular_velocity: f32, dt: f32) -> (f32, f32) {
+        let half_track = self.profile.track_width / 2.0;
+        let (mut left, mut right) = (
+            forward - angular_velocity * half_track,
+            forward + angular_velocity * half_track,
+        );
+        // Slow both sides down evenly if either would have to go too fast, to keep the curvature
+        let fastest = left.abs().max(right.abs());
+        if fastest > self.profile.max_wheel_speed {
+            left *= self.profile.max_wheel_speed / fastest;
+            right *= self.profile.max_wheel_speed / fastest;
+        }
+
+        let max_change = self.profile.max_wheel_accel * dt;
+        self.left_speed += (left - self.left_speed).clamp(-max_change, max_change);
+        self.right_speed += (right - self.right_speed).clamp(-max_change, max_change);
+
+        (
+            (self.left_speed + self.right_speed) / 2.0,
+            (self.right_speed - self.left_speed) / self.profile.track_width,
+        )
+    }
+}
+
+fn drive_differential(
+    mut q: Query<(
+        &mut DifferentialDrive,
+        &mut Velocity,
+        &mut AngularVelocity,
+        &Transform,
+    )>,
+    time: Res<Time>,
+) {
+    for (mut drive, mut vel, mut avel, transform) in &mut q {
+        let heading = Vec2::from_angle(transform.rotation.to_euler(EulerRot::ZYX).0);
+        let (forward, angular_velocity) =
+            drive.update(vel.0.dot(heading), avel.0, time.delta_seconds());
+        vel.0 = heading * forward;
+        avel.0 = angular_velocity;
+    }
+}
@@ -1,12 +1,13 @@
-use std::collections::HashSet as Set;
-use std::io::Write;
+use std::collections::{HashMap, HashSet as Set};
 
 use bevy::{color::palettes::css::*, prelude::*};
 use bevy_prototype_lyon::prelude::*;
 
-use serde::{Deserialize, Serialize};
+use robot_planner::{clearance, Edge, LoadError, NodeId, VisibilityGraph};
 
-use crate::{Mode, MouseWorldPos, SavePath};
+use crate::history::{EditHistory, GraphEdit};
+use crate::profile::RobotProfiles;
+use crate::{EditLayer, Mode, MouseWorldPos, SavePath};
 
 const UNITS_SCALE: Vec3 = Vec3::new(
     1.0 / crate::UNITS_SCALE_FACTOR,
@@ -19,7 +20,27 @@ const FILL: Srgba = WHITE;
 const STROKE: Srgba = GREEN;
 const HIGHLIGHT: Srgba = SKY_BLUE;
 const NEG_HIGHLIGHT: Srgba = RED;
-const HIGHLIGHT_2: Srgba = ORANGE;
+// Edges that pass through an obstacle
+const INVALID: Srgba = MAGENTA;
+// Edges with a cost multiplier or speed limit
+const STROKE_2: Srgba = YELLOW;
+
+// Speed limits cycled through with V, in m/s
+const SPEED_LIMIT_STEPS: [Option<f32>; 5] = [None, Some(3.0), Some(2.0), Some(1.0), Some(0.5)];
+const COST_MULTIPLIER_STEP: f32 = 0.5;
+
+// Number keys toggle the tag at the same position in the (alphabetical) tag list
+const TAG_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
 
 pub struct FieldGraphPlugin;
 impl Plugin for FieldGraphPlugin {
@@ -27,88 +48,145 @@ impl Plugin for FieldGraphPlugin {
         app.insert_resource(DrawnGraph::default())
             .insert_resource(EditState::Normal)
             .insert_resource(Hovered::default())
-            .add_systems(Startup, init_field_graph.before(draw_field_graph))
+            .insert_resource(EditHistory::default())
+            .add_event::<RedrawGraph>()
             .add_systems(Startup, draw_field_graph)
             .add_systems(
                 Update,
                 (
                     update_mouse_state,
                     mouse_interaction.after(update_mouse_state),
+                    generate_roadmap.after(mouse_interaction),
                 )
+                    .run_if(in_state(EditLayer::Graph)),
+            )
+            .add_systems(
+                Update,
+                undo_redo
+                    .after(mouse_interaction)
                     .run_if(in_state(Mode::EditGraph)),
             )
-            .add_systems(Update, save_field_graph)
-            .add_systems(OnExit(Mode::EditGraph), on_exit_edit_mode);
+            .add_systems(Update, (redraw_on_request, save_field_graph))
+            .add_systems(OnEnter(Mode::EditGraph), print_tag_keys)
+            .add_systems(OnExit(EditLayer::Graph), on_exit_edit_mode);
     }
 }
 
-fn init_field_graph(save_path: Res<SavePath>, mut commands: Commands) {
-    type E = Box<dyn std::error::Error>;
-    let mut graph = std::fs::File::open(&save_path.0)
-        .map_err(E::from)
-        .and_then(|f| serde_json::from_reader::<_, FieldGraph>(f).map_err(E::from))
-        .or_else(|_| serde_json::from_str(include_str!("../assets/default-graph.json")))
-        .unwrap();
-    for node in graph.sg.nodes.iter_mut() {
-        node.y *= -1.0;
-        std::mem::swap(&mut node.x, &mut node.y);
-        *node += ORIGIN_OFFSET;
+/// The field graph being shown and edited, in world coordinates.
+#[derive(Resource, Clone, Deref, DerefMut)]
+pub struct FieldGraph(pub robot_planner::FieldGraph);
+
+/// Reads the graph to edit, or starts from the default graph if the file doesn't exist yet.
+pub fn read_field_graph_or_default(path: &str) -> Result<FieldGraph, String> {
+    if !std::path::Path::new(path).exists() {
+        eprintln!("{path} doesn't exist yet, starting from the default graph");
+        let graph =
+            robot_planner::FieldGraph::from_json(include_str!("../assets/default-graph.json"))
+                .unwrap();
+        return Ok(to_world(graph));
     }
-    commands.insert_resource(graph);
+    read_field_graph(path)
 }
 
-#[derive(Resource, Serialize, Deserialize, Clone)]
-pub struct FieldGraph {
-    #[serde(flatten)]
-    pub sg: SpatialGraph,
-    pub shoot_idxs: Set<usize>,
+/// Reads a graph file, in world coordinates. Problems that would break pathfinding make it fail,
+/// and the rest are printed as warnings.
+pub fn read_field_graph(path: &str) -> Result<FieldGraph, String> {
+    let (graph, problems) = robot_planner::FieldGraph::load_checked(path).map_err(|e| match e {
+        LoadError::Io(e) => format!("Couldn't read graph {path}: {e}"),
+        LoadError::Parse(e) => format!("Invalid graph {path}: {e}"),
+    })?;
+    let (warnings, errors): (Vec<_>, Vec<_>) = problems
+        .into_iter()
+        .partition(|problem| problem.problem.is_warning());
+    for warning in warnings {
+        eprintln!("Warning: {path}: {warning}");
+    }
+    if !errors.is_empty() {
+        let errors: Vec<_> = errors.iter().map(|e| format!("{path}: {e}")).collect();
+        return Err(format!("Invalid graph {path}:\n{}", errors.join("\n")));
+    }
+    Ok(to_world(graph))
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct SpatialGraph {
-    pub nodes: Vec<Vec2>,
-    pub edges: Vec<(usize, usize)>,
+fn to_world(mut graph: robot_planner::FieldGraph) -> FieldGraph {
+    for point in graph.points_mut() {
+        *point = field_to_world(*point);
+    }
+    FieldGraph(graph)
 }
 
-impl SpatialGraph {
-    /// Finds a given edge, if it exists. Disregards order of node indices in tuple.
-    fn find_edge(&self, (start_i, end_i): (usize, usize)) -> Option<usize> {
-        self.edges
-            .iter()
-            .enumerate()
-            .find_map(|(i, &tup)| (tup == (start_i, end_i) || tup == (end_i, start_i)).then_some(i))
-    }
+/// Converts a point from field coordinates, which graph files and the mouse readout use,
+/// to world coordinates. Field coordinates have the axes swapped and a different origin.
+pub fn field_to_world(point: Vec2) -> Vec2 {
+    Vec2::new(-point.y, point.x) + ORIGIN_OFFSET
+}
 
-    fn connected_edges(&self, node_i: usize) -> impl DoubleEndedIterator<Item = usize> + '_ {
-        self.edges
-            .iter()
-            .copied()
-            .enumerate()
-            .filter(move |&(_, (a, b))| a == node_i || b == node_i)
-            .map(|tup| tup.0)
-    }
+/// Converts a point from world coordinates to field coordinates.
+pub fn world_to_field(point: Vec2) -> Vec2 {
+    let point = point - ORIGIN_OFFSET;
+    Vec2::new(point.y, -point.x)
 }
 
 #[derive(Resource, Default)]
 struct DrawnGraph {
-    nodes: Vec<Entity>,
+    nodes: HashMap<NodeId, Entity>,
     edges: Vec<Entity>,
 }
 
 fn draw_field_graph(graph: Res<FieldGraph>, mut drawn: ResMut<DrawnGraph>, mut commands: Commands) {
-    for (i, &node) in graph.sg.nodes.iter().enumerate() {
-        drawn.nodes.push(draw_node(
-            node,
-            STROKE,
-            point_fill(&graph, i),
-            &mut commands,
-        ));
+    spawn_graph(&graph, &mut drawn, &mut commands);
+}
+
+fn spawn_graph(graph: &FieldGraph, drawn: &mut DrawnGraph, commands: &mut Commands) {
+    for (&id, &node) in &graph.sg.nodes {
+        drawn
+            .nodes
+            .insert(id, draw_node(node, STROKE, point_fill(graph, id), commands));
     }
     for &edge in &graph.sg.edges {
-        let p1 = graph.sg.nodes[edge.0];
-        let p2 = graph.sg.nodes[edge.1];
-        drawn.edges.push(draw_edge(p1, p2, STROKE, &mut commands));
+        drawn
+            .edges
+            .push(draw_graph_edge(edge, graph, STROKE, commands));
+    }
+}
+
+/// Requests the graph to be redrawn, e.g. because obstacles changed which edges are blocked.
+#[derive(Event)]
+pub struct RedrawGraph;
+
+fn redraw_on_request(
+    graph: Res<FieldGraph>,
+    mut drawn: ResMut<DrawnGraph>,
+    mut hovered: ResMut<Hovered>,
+    mut reader: EventReader<RedrawGraph>,
+    mut commands: Commands,
+) {
+    if reader.is_empty() {
+        return;
+    }
+    reader.clear();
+    redraw_field_graph(&graph, &mut drawn, &mut hovered, &mut commands);
+}
+
+/// Despawns and redraws the entire graph, e.g. after the graph was changed by undo/redo.
+fn redraw_field_graph(
+    graph: &FieldGraph,
+    drawn: &mut DrawnGraph,
+    hovered: &mut Hovered,
+    commands: &mut Commands,
+) {
+    for id in drawn
+        .nodes
+        .drain()
+        .map(|t| t.1)
+        .chain(drawn.edges.drain(..))
+    {
+        commands.entity(id).despawn();
     }
+    spawn_graph(graph, drawn, commands);
+    // Everything is redrawn unhighlighted, so let `update_mouse_state` highlight it again.
+    hovered.node.1 = None;
+    hovered.edges.1.clear();
 }
 
 fn draw_node(
@@ -134,17 +212,27 @@ fn draw_node(
         .id()
 }
 
-fn draw_edge(p1: Vec2, p2: Vec2, color: Srgba, commands: &mut Commands) -> Entity {
+fn draw_edge(p1: Vec2, p2: Vec2, one_way: bool, color: Srgba, commands: &mut Commands) -> Entity {
     let zmod = match color {
         NEG_HIGHLIGHT => 0.12,
         HIGHLIGHT => 0.11,
         _ => 0.1,
     };
-    let shape = shapes::Line(p1, p2);
+    let mut path = PathBuilder::new();
+    path.move_to(p1);
+    path.line_to(p2);
+    if one_way {
+        // Arrowhead at the midpoint, pointing towards `p2`
+        let dir = (p2 - p1).normalize_or_zero() * 0.08;
+        let (mid, perp) = ((p1 + p2) / 2.0, dir.perp());
+        path.move_to(mid - dir + perp);
+        path.line_to(mid + dir);
+        path.line_to(mid - dir - perp);
+    }
     commands
         .spawn((
             ShapeBundle {
-                path: GeometryBuilder::build_as(&shape),
+                path: path.build(),
                 ..Default::default()
             },
             Stroke::new(color, 10.0 / crate::UNITS_SCALE_FACTOR),
@@ -153,17 +241,37 @@ fn draw_edge(p1: Vec2, p2: Vec2, color: Srgba, commands: &mut Commands) -> Entit
         .id()
 }
 
+fn draw_graph_edge(
+    edge: Edge,
+    graph: &FieldGraph,
+    color: Srgba,
+    commands: &mut Commands,
+) -> Entity {
+    let color = match color {
+        STROKE if graph.edge_blocked(&edge) => INVALID,
+        STROKE if edge.has_cost_attrs() => STROKE_2,
+        _ => color,
+    };
+    draw_edge(
+        graph.sg.nodes[&edge.start],
+        graph.sg.nodes[&edge.end],
+        edge.one_way,
+        color,
+        commands,
+    )
+}
+
 fn replace_node(
-    i: usize,
+    node: NodeId,
     stroke: Srgba,
     fill: Srgba,
     graph: &FieldGraph,
     drawn: &mut DrawnGraph,
     commands: &mut Commands,
 ) {
-    if let Some(&id) = drawn.nodes.get(i) {
-        commands.entity(id).despawn();
-        drawn.nodes[i] = draw_node(graph.sg.nodes[i], stroke, fill, commands);
+    if let Some(id) = drawn.nodes.get_mut(&node) {
+        commands.entity(*id).despawn();
+        *id = draw_node(graph.sg.nodes[&node], stroke, fill, commands);
     }
 }
 
@@ -176,53 +284,69 @@ fn replace_edge(
 ) {
     if let Some(&id) = drawn.edges.get(i) {
         commands.entity(id).despawn();
-        let edge = graph.sg.edges[i];
-        drawn.edges[i] = draw_edge(
-            graph.sg.nodes[edge.0],
-            graph.sg.nodes[edge.1],
-            color,
-            commands,
-        );
+        drawn.edges[i] = draw_graph_edge(graph.sg.edges[i], graph, color, commands);
     }
 }
 
 fn split_edges<T: std::ops::Deref<Target = usize>>(
-    new_i: usize,
+    new_node: NodeId,
     edges: impl IntoIterator<Item = T>,
     graph: &mut FieldGraph,
+    history: &mut EditHistory,
     drawn: &mut DrawnGraph,
     commands: &mut Commands,
 ) {
     let mut edges_to_replace: Vec<_> = edges.into_iter().map(|x| *x).collect();
     edges_to_replace.sort_unstable();
     for edge_i in edges_to_replace.into_iter().rev() {
-        let (start_i, end_i) = graph.sg.edges.remove(edge_i);
-        let (start, end) = (graph.sg.nodes[start_i], graph.sg.nodes[end_i]);
-
-        graph.sg.edges.push((start_i, new_i));
-        graph.sg.edges.push((new_i, end_i));
-
+        let edge = graph.sg.edges[edge_i];
+        history.apply(GraphEdit::RemoveEdge { i: edge_i, edge }, graph);
+
+        // Both halves keep the attributes of the original edge
+        let halves = [
+            Edge {
+                end: new_node,
+                ..edge
+            },
+            Edge {
+                start: new_node,
+                ..edge
+            },
+        ];
         commands.entity(drawn.edges.remove(edge_i)).despawn();
-        drawn
-            .edges
-            .push(draw_edge(start, graph.sg.nodes[new_i], STROKE, commands));
-        drawn
-            .edges
-            .push(draw_edge(graph.sg.nodes[new_i], end, STROKE, commands));
+        for half in halves {
+            push_edge(half, graph, history);
+            drawn
+                .edges
+                .push(draw_graph_edge(half, graph, STROKE, commands));
+        }
     }
 }
 
+fn push_node(pos: Vec2, graph: &mut FieldGraph, history: &mut EditHistory) -> NodeId {
+    let id = graph.sg.new_id();
+    history.apply(GraphEdit::AddNode { id, pos }, graph);
+    id
+}
+
+fn push_edge(edge: Edge, graph: &mut FieldGraph, history: &mut EditHistory) {
+    let i = graph.sg.edges.len();
+    history.apply(GraphEdit::AddEdge { i, edge }, graph);
+}
+
 #[derive(Resource, Clone, Copy)]
 enum EditState {
     Normal,
-    MakingEdge(usize, Option<Entity>),
-    DraggingNode(usize, Vec2),
+    MakingEdge(NodeId, Option<Entity>),
+    // (node ID, node position relative to mouse, node position when the drag started)
+    DraggingNode(NodeId, Vec2, Vec2),
 }
 
 #[derive(Resource, Clone, Default)]
 struct Hovered {
-    // (index of hovered, index of highlighted)
-    node: (Option<usize>, Option<usize>),
+    // (ID of hovered, ID of highlighted)
+    node: (Option<NodeId>, Option<NodeId>),
+    // (indices of hovered, indices of highlighted)
     edges: (Set<usize>, Set<usize>),
 }
 
@@ -255,6 +379,7 @@ fn update_mouse_state(
     mouse_click: Res<ButtonInput<MouseButton>>,
     time: Res<Time>,
     graph: Res<FieldGraph>,
+    mut history: ResMut<EditHistory>,
     mut edit_state: ResMut<EditState>,
     mut drawn: ResMut<DrawnGraph>,
     mut hovered: ResMut<Hovered>,
@@ -277,10 +402,7 @@ fn update_mouse_state(
             .sg
             .nodes
             .iter()
-            .copied()
-            .map(|n| n - pos)
-            .map(Vec2::length)
-            .enumerate()
+            .map(|(&id, &n)| (id, (n - pos).length()))
             .filter(|&(_, dist)| dist < 0.13)
             .min_by(|a, b| a.1.total_cmp(&b.1))
             .map(|tup| tup.0)
@@ -291,14 +413,15 @@ fn update_mouse_state(
         (true, false) => {
             let click_pos = drag_detector.click_pos.unwrap_or(mouse_pos.0);
             if let Some(i) = find_hovered_node(click_pos) {
-                let relative_pos = graph.sg.nodes[i] - click_pos;
+                let relative_pos = graph.sg.nodes[&i] - click_pos;
                 if let EditState::MakingEdge(_, Some(id)) = *edit_state {
                     commands.entity(id).despawn();
                 }
-                *edit_state = EditState::DraggingNode(i, relative_pos);
+                *edit_state = EditState::DraggingNode(i, relative_pos, graph.sg.nodes[&i]);
             }
         }
         (false, true) => {
+            record_drag(*edit_state, &graph, &mut history);
             *edit_state = EditState::Normal;
         }
         _ => {}
@@ -339,8 +462,8 @@ fn update_mouse_state(
                 .edges
                 .iter()
                 .enumerate()
-                .filter_map(|(i, &(start_i, end_i))| {
-                    let (start, end) = (graph.sg.nodes[start_i], graph.sg.nodes[end_i]);
+                .filter_map(|(i, edge)| {
+                    let (start, end) = (graph.sg.nodes[&edge.start], graph.sg.nodes[&edge.end]);
                     let (vec1, vec2) = (start - mouse_pos.0, end - mouse_pos.0);
                     (vec1.perp_dot(vec2).abs() < 0.1 && vec1.dot(vec2) < 0.0).then_some(i)
                 })
@@ -373,6 +496,7 @@ fn mouse_interaction(
     hovered: Res<Hovered>,
     mut edit_state: ResMut<EditState>,
     mut graph: ResMut<FieldGraph>,
+    mut history: ResMut<EditHistory>,
     mut drawn: ResMut<DrawnGraph>,
     mut commands: Commands,
 ) {
@@ -385,48 +509,34 @@ fn mouse_interaction(
         (EditState::Normal, Some(i)) if mouse_click.just_pressed(MouseButton::Right) => {
             // Reverse index list so that sequential deletion doesn't shift the indices being affected
             let edges_to_delete = graph.sg.connected_edges(i).rev().collect::<Vec<_>>();
-            let del_edge = |i| {
-                graph.sg.edges.remove(i);
-                commands.entity(drawn.edges.remove(i)).despawn();
-            };
-            edges_to_delete.into_iter().for_each(del_edge);
-            graph
-                .sg
-                .edges
-                .iter_mut()
-                .flat_map(|(a, b)| [a, b])
-                .filter(|v| **v > i)
-                .for_each(|v| *v -= 1);
-
-            graph.sg.nodes.remove(i);
-            commands.entity(drawn.nodes.remove(i)).despawn();
-
-            graph.shoot_idxs.remove(&i);
-            let to_shift = graph
-                .shoot_idxs
-                .iter()
-                .copied()
-                .filter(|&j| j > i)
-                .collect::<Vec<_>>();
-            for j in &to_shift {
-                graph.shoot_idxs.remove(j);
+            for edge_i in edges_to_delete {
+                let edge = graph.sg.edges[edge_i];
+                history.apply(GraphEdit::RemoveEdge { i: edge_i, edge }, &mut graph);
+                commands.entity(drawn.edges.remove(edge_i)).despawn();
             }
-            for j in to_shift {
-                graph.shoot_idxs.insert(j - 1);
+
+            let edit = GraphEdit::RemoveNode {
+                id: i,
+                pos: graph.sg.nodes[&i],
+                tags: graph.node_tags(i).map(str::to_owned).collect(),
+            };
+            history.apply(edit, &mut graph);
+            if let Some(id) = drawn.nodes.remove(&i) {
+                commands.entity(id).despawn();
             }
         }
         // Clicked empty space - create a new node and start drawing an edge from it
         (EditState::Normal, None) if mouse_click.just_pressed(MouseButton::Left) => {
-            let new_i = graph.sg.nodes.len();
-            graph.sg.nodes.push(mouse_pos.0);
+            let new_i = push_node(mouse_pos.0, &mut graph, &mut history);
             drawn
                 .nodes
-                .push(draw_node(mouse_pos.0, STROKE, FILL, &mut commands));
+                .insert(new_i, draw_node(mouse_pos.0, STROKE, FILL, &mut commands));
 
             split_edges(
                 new_i,
                 &hovered.edges.0,
                 &mut graph,
+                &mut history,
                 &mut drawn,
                 &mut commands,
             );
@@ -438,7 +548,8 @@ fn mouse_interaction(
             let mut edges_to_delete: Vec<_> = hovered.edges.0.iter().copied().collect();
             edges_to_delete.sort_unstable();
             for i in edges_to_delete.into_iter().rev() {
-                graph.sg.edges.remove(i);
+                let edge = graph.sg.edges[i];
+                history.apply(GraphEdit::RemoveEdge { i, edge }, &mut graph);
                 commands.entity(drawn.edges.remove(i)).despawn();
             }
         }
@@ -450,16 +561,19 @@ fn mouse_interaction(
                 commands.entity(id).despawn();
             }
             if let Some(existing_edge) = graph.sg.find_edge((start_i, end_i)) {
-                graph.sg.edges.remove(existing_edge);
+                let edge = graph.sg.edges[existing_edge];
+                let edit = GraphEdit::RemoveEdge {
+                    i: existing_edge,
+                    edge,
+                };
+                history.apply(edit, &mut graph);
                 commands.entity(drawn.edges.remove(existing_edge)).despawn();
             } else {
-                graph.sg.edges.push((start_i, end_i));
-                drawn.edges.push(draw_edge(
-                    graph.sg.nodes[start_i],
-                    graph.sg.nodes[end_i],
-                    STROKE,
-                    &mut commands,
-                ));
+                let edge = Edge::new(start_i, end_i);
+                push_edge(edge, &mut graph, &mut history);
+                drawn
+                    .edges
+                    .push(draw_graph_edge(edge, &graph, STROKE, &mut commands));
             }
             *edit_state = EditState::Normal;
         }
@@ -470,23 +584,21 @@ fn mouse_interaction(
             if let Some(id) = id_o {
                 commands.entity(id).despawn();
             }
-            let end_i = graph.sg.nodes.len();
-            graph.sg.nodes.push(mouse_pos.0);
-            graph.sg.edges.push((start_i, end_i));
+            let end_i = push_node(mouse_pos.0, &mut graph, &mut history);
+            let edge = Edge::new(start_i, end_i);
+            push_edge(edge, &mut graph, &mut history);
             drawn
                 .nodes
-                .push(draw_node(mouse_pos.0, STROKE, FILL, &mut commands));
-            drawn.edges.push(draw_edge(
-                graph.sg.nodes[start_i],
-                mouse_pos.0,
-                STROKE,
-                &mut commands,
-            ));
+                .insert(end_i, draw_node(mouse_pos.0, STROKE, FILL, &mut commands));
+            drawn
+                .edges
+                .push(draw_graph_edge(edge, &graph, STROKE, &mut commands));
 
             split_edges(
                 end_i,
                 &hovered.edges.0,
                 &mut graph,
+                &mut history,
                 &mut drawn,
                 &mut commands,
             );
@@ -508,11 +620,12 @@ fn mouse_interaction(
             let is_edge_deletion =
                 hovered_node.is_some_and(|end_i| graph.sg.find_edge((start_i, end_i)).is_some());
             let id = draw_edge(
-                graph.sg.nodes[start_i],
+                graph.sg.nodes[&start_i],
                 match hovered_node {
-                    Some(i) => graph.sg.nodes[i],
+                    Some(i) => graph.sg.nodes[&i],
                     None => mouse_pos.0,
                 },
+                false,
                 if is_edge_deletion {
                     NEG_HIGHLIGHT
                 } else {
@@ -523,9 +636,10 @@ fn mouse_interaction(
             *edit_state = EditState::MakingEdge(start_i, Some(id));
         }
         // Dragging a node
-        (EditState::DraggingNode(i, relative_pos), _) => {
+        // The move is only recorded in the history once the drag ends, so that the whole drag is undone at once.
+        (EditState::DraggingNode(i, relative_pos, _), _) => {
             let new_pos = mouse_pos.0 + relative_pos;
-            graph.sg.nodes[i] = new_pos;
+            graph.sg.nodes.insert(i, new_pos);
 
             replace_node(
                 i,
@@ -538,19 +652,20 @@ fn mouse_interaction(
 
             for edge_i in graph.sg.connected_edges(i) {
                 commands.entity(drawn.edges[edge_i]).despawn();
-                let edge = graph.sg.edges[edge_i];
-                drawn.edges[edge_i] = draw_edge(
-                    graph.sg.nodes[edge.0],
-                    graph.sg.nodes[edge.1],
-                    STROKE,
-                    &mut commands,
-                );
+                drawn.edges[edge_i] =
+                    draw_graph_edge(graph.sg.edges[edge_i], &graph, STROKE, &mut commands);
             }
         }
-        // Pressed W; toggle shoot waypoint
-        (EditState::Normal, Some(i)) if key_press.just_pressed(KeyCode::KeyW) => {
-            if !graph.shoot_idxs.remove(&i) {
-                graph.shoot_idxs.insert(i);
+        // Pressed a number key; toggle the corresponding tag, or clear all tags for 0
+        (EditState::Normal, Some(i))
+            if key_press.any_just_pressed(TAG_KEYS.into_iter().chain([KeyCode::Digit0])) =>
+        {
+            let tags: Vec<String> = match TAG_KEYS.iter().position(|&k| key_press.just_pressed(k)) {
+                Some(n) => graph.tags.keys().nth(n).cloned().into_iter().collect(),
+                None => graph.node_tags(i).map(str::to_owned).collect(),
+            };
+            for tag in tags {
+                history.apply(GraphEdit::ToggleTag(i, tag), &mut graph);
             }
             replace_node(
                 i,
@@ -561,20 +676,184 @@ fn mouse_interaction(
                 &mut commands,
             );
         }
+        // Pressed D; toggle hovered edges between one-way and two-way, or reverse them if shift is held
+        (EditState::Normal, None) if key_press.just_pressed(KeyCode::KeyD) => {
+            let reverse = key_press.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+            for &i in &hovered.edges.0 {
+                let from = graph.sg.edges[i];
+                let to = if reverse {
+                    from.reversed()
+                } else {
+                    Edge {
+                        one_way: !from.one_way,
+                        ..from
+                    }
+                };
+                history.apply(GraphEdit::SetEdge { i, from, to }, &mut graph);
+                replace_edge(i, HIGHLIGHT, &graph, &mut drawn, &mut commands);
+            }
+        }
+        // Pressed C; raise the cost multiplier of hovered edges, or lower it if shift is held
+        // Pressed V; step down the speed limit of hovered edges, or step it up if shift is held
+        (EditState::Normal, None) if key_press.any_just_pressed([KeyCode::KeyC, KeyCode::KeyV]) => {
+            let shift = key_press.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+            for &i in &hovered.edges.0 {
+                let from = graph.sg.edges[i];
+                let mut to = from;
+                if key_press.just_pressed(KeyCode::KeyC) {
+                    let step = if shift {
+                        -COST_MULTIPLIER_STEP
+                    } else {
+                        COST_MULTIPLIER_STEP
+                    };
+                    to.cost_multiplier = (from.cost_multiplier + step).max(1.0);
+                } else {
+                    let n = SPEED_LIMIT_STEPS.len();
+                    let cur = SPEED_LIMIT_STEPS
+                        .iter()
+                        .position(|&s| s == from.max_speed)
+                        .unwrap_or(0);
+                    to.max_speed =
+                        SPEED_LIMIT_STEPS[(if shift { cur + n - 1 } else { cur + 1 }) % n];
+                }
+                eprintln!(
+                    "Edge cost multiplier: {}, speed limit: {}",
+                    to.cost_multiplier,
+                    to.max_speed
+                        .map_or_else(|| "none".to_owned(), |s| format!("{s} m/s")),
+                );
+                history.apply(GraphEdit::SetEdge { i, from, to }, &mut graph);
+                replace_edge(i, HIGHLIGHT, &graph, &mut drawn, &mut commands);
+            }
+        }
         _ => {}
     }
+    history.commit();
+}
+
+/// Records a finished node drag in the history as a single move.
+fn record_drag(edit_state: EditState, graph: &FieldGraph, history: &mut EditHistory) {
+    if let EditState::DraggingNode(id, _, from) = edit_state {
+        let to = graph.sg.nodes[&id];
+        if from != to {
+            history.record(GraphEdit::MoveNode { id, from, to });
+        }
+    }
+}
+
+// G to replace the graph with a visibility graph around the obstacles, Shift+G to merge one into it
+#[allow(clippy::too_many_arguments)]
+fn generate_roadmap(
+    key_press: Res<ButtonInput<KeyCode>>,
+    edit_state: Res<EditState>,
+    mut graph: ResMut<FieldGraph>,
+    mut history: ResMut<EditHistory>,
+    mut drawn: ResMut<DrawnGraph>,
+    mut hovered: ResMut<Hovered>,
+    profiles: Res<RobotProfiles>,
+    mut commands: Commands,
+) {
+    if !key_press.just_pressed(KeyCode::KeyG) || !matches!(*edit_state, EditState::Normal) {
+        return;
+    }
+    let merge = key_press.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    // Leave room for the biggest robot, so that every robot can use the roadmap
+    let roadmap = VisibilityGraph::new(&graph.obstacles, clearance(profiles.max_bumper_size()));
+
+    let existing: Vec<_> = graph.sg.nodes.iter().map(|(&id, &pos)| (id, pos)).collect();
+    if !merge {
+        for i in (0..graph.sg.edges.len()).rev() {
+            let edge = graph.sg.edges[i];
+            history.apply(GraphEdit::RemoveEdge { i, edge }, &mut graph);
+        }
+        for &(id, pos) in &existing {
+            let tags = graph.node_tags(id).map(str::to_owned).collect();
+            history.apply(GraphEdit::RemoveNode { id, pos, tags }, &mut graph);
+        }
+    }
+
+    let ids: Vec<_> = roadmap
+        .nodes
+        .iter()
+        .map(|&pos| push_node(pos, &mut graph, &mut history))
+        .collect();
+    for &(a, b) in &roadmap.edges {
+        push_edge(Edge::new(ids[a], ids[b]), &mut graph, &mut history);
+    }
+    if merge {
+        // Connect the existing graph to the new nodes wherever there's a clear line
+        for &(id, pos) in &existing {
+            for (&new_id, &new_pos) in ids.iter().zip(&roadmap.nodes) {
+                if roadmap.visible(pos, new_pos) {
+                    push_edge(Edge::new(id, new_id), &mut graph, &mut history);
+                }
+            }
+        }
+    }
+    history.commit();
+    eprintln!(
+        "Generated {} nodes and {} edges around {} obstacles",
+        roadmap.nodes.len(),
+        roadmap.edges.len(),
+        graph.obstacles.len(),
+    );
+
+    redraw_field_graph(&graph, &mut drawn, &mut hovered, &mut commands);
+}
+
+// Ctrl+Z to undo, Ctrl+Shift+Z to redo
+#[allow(clippy::too_many_arguments)]
+fn undo_redo(
+    key_press: Res<ButtonInput<KeyCode>>,
+    mouse_click: Res<ButtonInput<MouseButton>>,
+    mut edit_state: ResMut<EditState>,
+    mut graph: ResMut<FieldGraph>,
+    mut history: ResMut<EditHistory>,
+    mut drawn: ResMut<DrawnGraph>,
+    mut hovered: ResMut<Hovered>,
+    mut commands: Commands,
+) {
+    let ctrl = key_press.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = key_press.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    // Not while a node or obstacle vertex is being dragged
+    if !ctrl || !key_press.just_pressed(KeyCode::KeyZ) || mouse_click.pressed(MouseButton::Left) {
+        return;
+    }
+    match *edit_state {
+        EditState::Normal => {}
+        // Indices held by an in-progress edge may not survive the undo, so cancel it
+        EditState::MakingEdge(_, id_o) => {
+            if let Some(id) = id_o {
+                commands.entity(id).despawn();
+            }
+            *edit_state = EditState::Normal;
+        }
+        EditState::DraggingNode(..) => return,
+    }
+
+    let changed = if shift {
+        history.redo(&mut graph)
+    } else {
+        history.undo(&mut graph)
+    };
+    if changed {
+        redraw_field_graph(&graph, &mut drawn, &mut hovered, &mut commands);
+    }
 }
 
 fn on_exit_edit_mode(
     mut edit_state: ResMut<EditState>,
     hovered: Res<Hovered>,
     graph: Res<FieldGraph>,
+    mut history: ResMut<EditHistory>,
     mut drawn: ResMut<DrawnGraph>,
     mut commands: Commands,
 ) {
     if let EditState::MakingEdge(_, Some(id)) = *edit_state {
         commands.entity(id).despawn();
     }
+    record_drag(*edit_state, &graph, &mut history);
+    history.commit();
     if let Some(i) = hovered.node.1 {
         replace_node(
             i,
@@ -600,32 +879,31 @@ fn save_field_graph(
         return;
     }
 
-    let mut save_graph = graph.clone();
-    for node in save_graph.sg.nodes.iter_mut() {
-        *node -= ORIGIN_OFFSET;
-        std::mem::swap(&mut node.x, &mut node.y);
-        node.y *= -1.0;
-    }
-    // Weird error juggling shenanigans (rust devs stabilize try blocks pls)
-    type E = Box<dyn std::error::Error>;
-    if let Err(e) = serde_json::to_string_pretty(&save_graph)
-        .map_err(E::from)
-        .and_then(|serialized| {
-            std::fs::File::create(&save_path.0)
-                .and_then(|mut f| write!(f, "{serialized}"))
-                .map_err(E::from)
-        })
-    {
+    let mut save_graph = graph.0.clone();
+    for point in save_graph.points_mut() {
+        *point = world_to_field(*point);
+    }
+    if let Err(e) = save_graph.save(&save_path.0) {
         eprintln!("{e}");
     } else {
         eprintln!("Saved to {}", save_path.0);
     }
 }
 
-fn point_fill(graph: &FieldGraph, i: usize) -> Srgba {
-    if graph.shoot_idxs.contains(&i) {
-        HIGHLIGHT_2
-    } else {
-        FILL
-    }
+fn point_fill(graph: &FieldGraph, id: NodeId) -> Srgba {
+    // Nodes with several tags take the color of the first one
+    graph.node_tags(id).next().map_or(FILL, |name| {
+        let color = graph.tags[name].color;
+        Srgba::rgba_u8(color.r, color.g, color.b, color.a)
+    })
+}
+
+fn print_tag_keys(graph: Res<FieldGraph>) {
+    let keys = graph
+        .tags
+        .keys()
+        .zip(1..=TAG_KEYS.len())
+        .map(|(name, n)| format!("{n}: {name}"))
+        .collect::<Vec<_>>();
+    eprintln!("Tag keys: {}, 0: clear", keys.join(", "));
 }
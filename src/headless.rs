@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{field_to_world, read_field_graph, world_to_field};
+use crate::physics::PHYSICS_RATE;
+use crate::profile::RobotProfiles;
+use crate::robot::{
+    FollowMode, PathFollower, PathSmoothing, RecomputeRobotPath, Robot, TargetPosition,
+};
+
+fn default_timeout() -> f32 {
+    60.0
+}
+
+/// A batch run of the simulation: where each robot is sent, and how it drives there.
+/// Positions are in field coordinates, like graph files.
+#[derive(Deserialize)]
+pub struct Scenario {
+    /// What each robot does, starting from robot 1. Robots left out stay where they start.
+    pub robots: Vec<RobotTask>,
+    #[serde(default)]
+    pub smoothing: bool,
+    /// Simulated time after which robots that haven't arrived are given up on, in s.
+    #[serde(default = "default_timeout")]
+    pub timeout: f32,
+}
+
+#[derive(Deserialize)]
+pub struct RobotTask {
+    pub target: [f32; 2],
+    /// Keeps the default if left out.
+    pub follow_mode: Option<FollowMode>,
+}
+
+/// How one robot did, as printed at the end of the run.
+#[derive(Serialize)]
+struct RobotResult {
+    robot: usize,
+    arrived: bool,
+    /// s, if the robot arrived
+    time: Option<f32>,
+    /// m driven, if the robot arrived
+    distance: Option<f32>,
+    /// m, if the robot arrived
+    mean_tracking_error: Option<f32>,
+    max_tracking_error: Option<f32>,
+    /// Waypoints of the path it drove along, in field coordinates.
+    path: Vec<[f32; 2]>,
+}
+
+/// Runs a scenario on the given graph without a window, as fast as possible, and prints how
+/// each robot did as JSON.
+pub fn run(scenario_path: &str, graph_path: &str, profiles: RobotProfiles) -> Result<(), String> {
+    let scenario: Scenario = std::fs::read_to_string(scenario_path)
+        .map_err(|e| format!("Couldn't read scenario {scenario_path}: {e}"))
+        .and_then(|text| {
+            serde_json::from_str(&text)
+                .map_err(|e| format!("Invalid scenario {scenario_path}: {e}"))
+        })?;
+    if scenario.robots.len() > profiles.0.len() {
+        return Err(format!(
+            "Scenario has {} robots, but there are only {}",
+            scenario.robots.len(),
+            profiles.0.len()
+        ));
+    }
+    let graph = read_field_graph(graph_path)?;
+
+    let timestep = Duration::from_secs_f64(1.0 / PHYSICS_RATE);
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins((
+            crate::physics::PhysicsPlugin,
+            crate::profile::ProfilePlugin,
+            crate::robot::RobotPlugin,
+            crate::swerve::SwervePlugin,
+            crate::differential::DifferentialPlugin,
+        ))
+        // Every update is exactly one physics step, however long it really takes
+        .insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+        .insert_resource(graph)
+        .insert_resource(profiles);
+    app.finish();
+    app.cleanup();
+    // Startup spawns the robots
+    app.update();
+
+    let world = app.world_mut();
+    world.resource_mut::<PathSmoothing>().0 = scenario.smoothing;
+    let mut q = world.query::<(Entity, &Robot, &mut PathFollower, &mut TargetPosition)>();
+    let mut requests = Vec::new();
+    for (id, robot, mut follower, mut target) in q.iter_mut(world) {
+        let Some(task) = scenario.robots.get(robot.0) else {
+            continue;
+        };
+        target.0 = field_to_world(Vec2::from(task.target));
+        if let Some(mode) = task.follow_mode {
+            follower.mode = mode;
+        }
+        requests.push(RecomputeRobotPath(id));
+    }
+    world.send_event_batch(requests);
+
+    let steps = (scenario.timeout as f64 * PHYSICS_RATE).ceil() as usize;
+    let mut q = app.world_mut().query::<(&Robot, &PathFollower)>();
+    for _ in 0..steps {
+        app.update();
+        let world = app.world_mut();
+        let done = q
+            .iter(world)
+            .filter(|(robot, _)| robot.0 < scenario.robots.len())
+            .all(|(_, follower)| follower.last_run.is_some());
+        if done {
+            break;
+        }
+    }
+
+    let mut results: Vec<RobotResult> = q
+        .iter(app.world())
+        .filter(|(robot, _)| robot.0 < scenario.robots.len())
+        .map(|(robot, follower)| {
+            let run = follower.last_run.as_ref();
+            RobotResult {
+                robot: robot.0 + 1,
+                arrived: run.is_some(),
+                time: run.map(|run| run.time),
+                distance: run.map(|run| run.distance),
+                mean_tracking_error: run.map(|run| run.mean_error),
+                max_tracking_error: run.map(|run| run.max_error),
+                path: run
+                    .map(|run| {
+                        run.path
+                            .iter()
+                            .map(|wp| world_to_field(wp.pos).to_array())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            }
+        })
+        .collect();
+    results.sort_by_key(|result| result.robot);
+    println!("{}", serde_json::to_string_pretty(&results).unwrap());
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use bevy::prelude::*;
+
+use robot_planner::{Edge, NodeId, Obstacle};
+
+use crate::graph::FieldGraph;
+
+/// A single reversible modification to a [`FieldGraph`].
+#[derive(Clone, Debug)]
+pub enum GraphEdit {
+    AddNode {
+        id: NodeId,
+        pos: Vec2,
+    },
+    /// Removes a node that has no edges.
+    RemoveNode {
+        id: NodeId,
+        pos: Vec2,
+        tags: Vec<String>,
+    },
+    AddEdge {
+        i: usize,
+        edge: Edge,
+    },
+    RemoveEdge {
+        i: usize,
+        edge: Edge,
+    },
+    /// Replaces the edge at the given index, e.g. to change its direction.
+    SetEdge {
+        i: usize,
+        from: Edge,
+        to: Edge,
+    },
+    MoveNode {
+        id: NodeId,
+        from: Vec2,
+        to: Vec2,
+    },
+    ToggleTag(NodeId, String),
+    AddObstacle {
+        i: usize,
+        obstacle: Obstacle,
+    },
+    RemoveObstacle {
+        i: usize,
+        obstacle: Obstacle,
+    },
+    MoveVertex {
+        i: usize,
+        j: usize,
+        from: Vec2,
+        to: Vec2,
+    },
+}
+
+impl GraphEdit {
+    pub fn apply(&self, graph: &mut FieldGraph) {
+        match *self {
+            Self::AddNode { id, pos } => {
+                graph.sg.nodes.insert(id, pos);
+            }
+            Self::RemoveNode { id, .. } => {
+                graph.sg.nodes.remove(&id);
+                for tag in graph.tags.values_mut() {
+                    tag.nodes.remove(&id);
+                }
+            }
+            Self::AddEdge { i, edge } => graph.sg.edges.insert(i, edge),
+            Self::RemoveEdge { i, .. } => {
+                graph.sg.edges.remove(i);
+            }
+            Self::SetEdge { i, to, .. } => graph.sg.edges[i] = to,
+            Self::MoveNode { id, to, .. } => {
+                graph.sg.nodes.insert(id, to);
+            }
+            Self::ToggleTag(id, ref tag) => toggle_tag(graph, id, tag),
+            Self::AddObstacle { i, ref obstacle } => graph.obstacles.insert(i, obstacle.clone()),
+            Self::RemoveObstacle { i, .. } => {
+                graph.obstacles.remove(i);
+            }
+            Self::MoveVertex { i, j, to, .. } => graph.obstacles[i].vertices[j] = to,
+        }
+    }
+
+    pub fn revert(&self, graph: &mut FieldGraph) {
+        match *self {
+            Self::AddNode { id, .. } => {
+                graph.sg.nodes.remove(&id);
+            }
+            Self::RemoveNode { id, pos, ref tags } => {
+                graph.sg.nodes.insert(id, pos);
+                for tag in tags {
+                    toggle_tag(graph, id, tag);
+                }
+            }
+            Self::AddEdge { i, .. } => {
+                graph.sg.edges.remove(i);
+            }
+            Self::RemoveEdge { i, edge } => graph.sg.edges.insert(i, edge),
+            Self::SetEdge { i, from, .. } => graph.sg.edges[i] = from,
+            Self::MoveNode { id, from, .. } => {
+                graph.sg.nodes.insert(id, from);
+            }
+            Self::ToggleTag(id, ref tag) => toggle_tag(graph, id, tag),
+            Self::AddObstacle { i, .. } => {
+                graph.obstacles.remove(i);
+            }
+            Self::RemoveObstacle { i, ref obstacle } => graph.obstacles.insert(i, obstacle.clone()),
+            Self::MoveVertex { i, j, from, .. } => graph.obstacles[i].vertices[j] = from,
+        }
+    }
+}
+
+fn toggle_tag(graph: &mut FieldGraph, id: NodeId, tag: &str) {
+    if let Some(tag) = graph.tags.get_mut(tag) {
+        if !tag.nodes.remove(&id) {
+            tag.nodes.insert(id);
+        }
+    }
+}
+
+/// Undo/redo stacks of graph edits.
+///
+/// Each step is the list of edits made by a single user action, so that e.g.
+/// deleting a node together with its edges is undone all at once.
+#[derive(Resource, Default)]
+pub struct EditHistory {
+    undo: Vec<Vec<GraphEdit>>,
+    redo: Vec<Vec<GraphEdit>>,
+    pending: Vec<GraphEdit>,
+}
+
+impl EditHistory {
+    /// Applies an edit to the graph and adds it to the pending step.
+    pub fn apply(&mut self, edit: GraphEdit, graph: &mut FieldGraph) {
+        edit.apply(graph);
+        self.record(edit);
+    }
+
+    /// Adds an edit that has already been applied to the pending step.
+    pub fn record(&mut self, edit: GraphEdit) {
+        self.pending.push(edit);
+    }
+
+    /// Closes the pending step, making it undoable.
+    pub fn commit(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.undo.push(std::mem::take(&mut self.pending));
+        self.redo.clear();
+    }
+
+    /// Reverts the last step. Returns whether there was anything to undo.
+    pub fn undo(&mut self, graph: &mut FieldGraph) -> bool {
+        self.commit();
+        let Some(step) = self.undo.pop() else {
+            return false;
+        };
+        step.iter().rev().for_each(|edit| edit.revert(graph));
+        self.redo.push(step);
+        true
+    }
+
+    /// Reapplies the last undone step. Returns whether there was anything to redo.
+    pub fn redo(&mut self, graph: &mut FieldGraph) -> bool {
+        self.commit();
+        let Some(step) = self.redo.pop() else {
+            return false;
+        };
+        step.iter().for_each(|edit| edit.apply(graph));
+        self.undo.push(step);
+        true
+    }
+}
@@ -1,38 +1,114 @@
+mod avoidance;
+mod cli;
+mod differential;
 mod graph;
+mod headless;
+mod history;
+mod obstacles;
 mod physics;
+mod pid;
+mod profile;
 mod robot;
+mod swerve;
+mod trajectory;
+mod tuning;
 
 use bevy::prelude::*;
+use clap::Parser;
 
 const BG_SCALE_FACTOR: f32 = 0.47;
 // const UNITS_SCALE_FACTOR: f32 = 237.18072;
 const UNITS_SCALE_FACTOR: f32 = 199.95529;
 
 fn main() {
-    let save_path = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "graph.json".to_owned());
-    App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "Robot Pathfinding Sim".to_owned(),
-                resizable: false,
-                ..Default::default()
-            }),
+    let cli = cli::Cli::parse();
+    let result = match cli.command.unwrap_or(cli::Command::Gui(cli.gui)) {
+        cli::Command::Gui(args) => profile::RobotProfiles::load(&args.robots, args.alliance)
+            .and_then(|profiles| run_gui(args, profiles)),
+        cli::Command::Run {
+            scenario,
+            graph,
+            robots,
+        } => profile::RobotProfiles::load(&robots, profile::Alliance::Blue)
+            .and_then(|profiles| headless::run(&scenario, &graph, profiles)),
+        cli::Command::Path {
+            graph,
+            from,
+            to,
+            max_speed,
+            bumper_size,
+        } => cli::path(&graph, from, to, max_speed, bumper_size),
+        cli::Command::Export {
+            graph,
+            from,
+            stops,
+            name,
+            out,
+            robot,
+        } => cli::export(&graph, from, &stops, &name, &out, robot.as_deref()),
+        cli::Command::Validate { graph, navgrid } => cli::validate(&graph, navgrid.as_deref()),
+        cli::Command::Convert { input, output, to } => cli::convert(&input, &output, to),
+    };
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run_gui(args: cli::GuiArgs, profiles: profile::RobotProfiles) -> Result<(), String> {
+    let graph = graph::read_field_graph_or_default(&args.graph)?;
+    let navgrid = args
+        .navgrid
+        .as_deref()
+        .map(obstacles::read_navgrid_obstacles)
+        .transpose()?;
+    let field_image = args
+        .field
+        .unwrap_or_else(|| format!("{}-half-field-2025.png", args.alliance.name()));
+    let mut app = App::new();
+    if let Some(navgrid) = navgrid {
+        app.insert_resource(navgrid);
+    }
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            title: "Robot Pathfinding Sim".to_owned(),
+            resizable: false,
             ..Default::default()
-        }))
-        .add_plugins(bevy_prototype_lyon::plugin::ShapePlugin)
-        .add_plugins((
-            graph::FieldGraphPlugin,
-            physics::PhysicsPlugin,
-            robot::RobotPlugin,
-        ))
-        .add_systems(Startup, (add_camera, set_background))
-        .insert_resource(SavePath(save_path))
-        .insert_resource(MouseWorldPos(Vec2::ZERO))
-        .insert_state(Mode::Normal)
-        .add_systems(Update, (set_window_size, mouse_hover, switch_modes))
-        .run();
+        }),
+        ..Default::default()
+    }))
+    .add_plugins(bevy_prototype_lyon::plugin::ShapePlugin)
+    .add_plugins((
+        graph::FieldGraphPlugin,
+        obstacles::ObstaclePlugin,
+        physics::PhysicsPlugin,
+        physics::TimeControlsPlugin,
+        profile::ProfilePlugin,
+        robot::RobotPlugin,
+        robot::RobotControlsPlugin,
+        swerve::SwervePlugin,
+        differential::DifferentialPlugin,
+        tuning::TuningPlugin,
+    ))
+    .add_systems(Startup, (add_camera, set_background))
+    .insert_resource(graph)
+    .insert_resource(SavePath(args.graph))
+    .insert_resource(FieldImage(field_image))
+    .insert_resource(profiles)
+    .insert_resource(MouseWorldPos(Vec2::ZERO))
+    .insert_state(Mode::Normal)
+    .add_sub_state::<EditLayer>()
+    .add_systems(
+        Update,
+        (
+            set_window_size,
+            mouse_hover,
+            switch_modes,
+            switch_edit_layers,
+        ),
+    )
+    .run();
+    Ok(())
 }
 
 #[derive(Resource)]
@@ -49,11 +125,19 @@ fn add_camera(mut commands: Commands) {
     });
 }
 
+// Path of the field image in the assets folder
+#[derive(Resource)]
+struct FieldImage(String);
+
 #[derive(Resource)]
 struct BackgroundHandle(Handle<Image>);
 
-fn set_background(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let texture = asset_server.load("blue-half-field-2025.png");
+fn set_background(
+    field_image: Res<FieldImage>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let texture = asset_server.load(&field_image.0);
     commands.insert_resource(BackgroundHandle(texture.clone()));
     commands.spawn(SpriteBundle {
         texture,
@@ -128,3 +212,28 @@ fn switch_modes(
         });
     }
 }
+
+/// What is being edited in edit mode.
+#[derive(SubStates, Default, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[source(Mode = Mode::EditGraph)]
+enum EditLayer {
+    #[default]
+    Graph,
+    Obstacles,
+}
+
+fn switch_edit_layers(
+    keys: Res<ButtonInput<KeyCode>>,
+    layer: Option<Res<State<EditLayer>>>,
+    mut next_layer: ResMut<NextState<EditLayer>>,
+) {
+    let Some(layer) = layer else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::KeyO) {
+        next_layer.set(match layer.get() {
+            EditLayer::Graph => EditLayer::Obstacles,
+            EditLayer::Obstacles => EditLayer::Graph,
+        });
+    }
+}
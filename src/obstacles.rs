@@ -0,0 +1,274 @@
+use bevy::{color::palettes::css::*, prelude::*};
+use bevy_prototype_lyon::prelude::*;
+
+use robot_planner::{LoadError, NavGrid, Obstacle};
+
+use crate::graph::{field_to_world, FieldGraph, RedrawGraph};
+use crate::history::{EditHistory, GraphEdit};
+use crate::{EditLayer, MouseWorldPos};
+
+const FILL: Srgba = RED;
+const STROKE: Srgba = DARK_RED;
+const HIGHLIGHT: Srgba = SKY_BLUE;
+
+pub struct ObstaclePlugin;
+impl Plugin for ObstaclePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DrawnObstacles::default())
+            .insert_resource(ObstacleEditState::Normal)
+            .add_systems(Update, sync_drawn_obstacles)
+            .add_systems(
+                Update,
+                mouse_interaction
+                    .before(sync_drawn_obstacles)
+                    .run_if(in_state(EditLayer::Obstacles)),
+            )
+            .add_systems(
+                Update,
+                import_navgrid
+                    .before(sync_drawn_obstacles)
+                    .run_if(in_state(EditLayer::Obstacles))
+                    .run_if(resource_exists::<NavGridObstacles>),
+            )
+            .add_systems(OnExit(EditLayer::Obstacles), on_exit_obstacle_mode);
+    }
+}
+
+/// Blocked areas of the PathPlanner navgrid given on the command line, in world coordinates.
+#[derive(Resource)]
+pub struct NavGridObstacles(pub Vec<Obstacle>);
+
+/// Reads a PathPlanner `navgrid.json` file, which is in field coordinates like graph files.
+pub fn read_navgrid_obstacles(path: &str) -> Result<NavGridObstacles, String> {
+    let navgrid = NavGrid::load(path).map_err(|e| match e {
+        LoadError::Io(e) => format!("Couldn't read navgrid {path}: {e}"),
+        LoadError::Parse(e) => format!("Invalid navgrid {path}: {e}"),
+    })?;
+    let mut obstacles = navgrid.obstacles();
+    for vertex in obstacles.iter_mut().flat_map(|o| &mut o.vertices) {
+        *vertex = field_to_world(*vertex);
+    }
+    Ok(NavGridObstacles(obstacles))
+}
+
+#[derive(Resource, Default)]
+struct DrawnObstacles {
+    // Copy of the obstacles that were drawn, to tell when they need to be redrawn
+    obstacles: Vec<Obstacle>,
+    entities: Vec<Entity>,
+}
+
+fn draw_obstacle(obstacle: &Obstacle, commands: &mut Commands) -> Entity {
+    let shape = shapes::Polygon {
+        points: obstacle.vertices.clone(),
+        closed: true,
+    };
+    commands
+        .spawn((
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&shape),
+                ..Default::default()
+            },
+            Fill::color(FILL.with_alpha(0.35)),
+            Stroke::new(STROKE, 8.0 / crate::UNITS_SCALE_FACTOR),
+        ))
+        .insert(Transform::from_xyz(0.0, 0.0, 0.05))
+        .id()
+}
+
+// Redraws obstacles whenever they change, whether from editing, undo/redo or loading.
+// Edges are redrawn too, since whether they cross an obstacle may have changed.
+fn sync_drawn_obstacles(
+    graph: Res<FieldGraph>,
+    mut drawn: ResMut<DrawnObstacles>,
+    mut writer: EventWriter<RedrawGraph>,
+    mut commands: Commands,
+) {
+    if drawn.obstacles == graph.obstacles {
+        return;
+    }
+    for id in drawn.entities.drain(..) {
+        commands.entity(id).despawn();
+    }
+    for obstacle in &graph.obstacles {
+        let id = draw_obstacle(obstacle, &mut commands);
+        drawn.entities.push(id);
+    }
+    drawn.obstacles.clone_from(&graph.obstacles);
+    writer.send(RedrawGraph);
+}
+
+#[derive(Resource)]
+enum ObstacleEditState {
+    Normal,
+    // (vertices placed so far, preview of the outline)
+    Drawing(Vec<Vec2>, Option<Entity>),
+    // (obstacle index, vertex index, vertex position when the drag started)
+    DraggingVertex(usize, usize, Vec2),
+}
+
+fn find_hovered_vertex(graph: &FieldGraph, pos: Vec2) -> Option<(usize, usize)> {
+    graph
+        .obstacles
+        .iter()
+        .enumerate()
+        .flat_map(|(i, obstacle)| {
+            obstacle
+                .vertices
+                .iter()
+                .enumerate()
+                .map(move |(j, &v)| ((i, j), (v - pos).length()))
+        })
+        .filter(|&(_, dist)| dist < 0.13)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|tup| tup.0)
+}
+
+fn draw_preview(vertices: &[Vec2], mouse_pos: Vec2, commands: &mut Commands) -> Entity {
+    let shape = shapes::Polygon {
+        points: vertices.iter().copied().chain([mouse_pos]).collect(),
+        closed: false,
+    };
+    commands
+        .spawn((
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&shape),
+                ..Default::default()
+            },
+            Stroke::new(HIGHLIGHT, 8.0 / crate::UNITS_SCALE_FACTOR),
+        ))
+        .insert(Transform::from_xyz(0.0, 0.0, 0.13))
+        .id()
+}
+
+fn mouse_interaction(
+    mouse_pos: Res<MouseWorldPos>,
+    mouse_click: Res<ButtonInput<MouseButton>>,
+    mut edit_state: ResMut<ObstacleEditState>,
+    mut graph: ResMut<FieldGraph>,
+    mut history: ResMut<EditHistory>,
+    mut commands: Commands,
+) {
+    let left = mouse_click.just_pressed(MouseButton::Left);
+    let right = mouse_click.just_pressed(MouseButton::Right);
+    match &mut *edit_state {
+        ObstacleEditState::Normal if left => {
+            // Clicked a vertex - start dragging it
+            if let Some((i, j)) = find_hovered_vertex(&graph, mouse_pos.0) {
+                let from = graph.obstacles[i].vertices[j];
+                *edit_state = ObstacleEditState::DraggingVertex(i, j, from);
+            }
+            // Clicked anywhere else - start drawing a new obstacle
+            else {
+                *edit_state = ObstacleEditState::Drawing(vec![mouse_pos.0], None);
+            }
+        }
+        // Right clicked an obstacle - delete it
+        ObstacleEditState::Normal if right => {
+            let hovered = graph
+                .obstacles
+                .iter()
+                .rposition(|obstacle| obstacle.contains(mouse_pos.0));
+            if let Some(i) = hovered {
+                let obstacle = graph.obstacles[i].clone();
+                history.apply(GraphEdit::RemoveObstacle { i, obstacle }, &mut graph);
+            }
+        }
+        ObstacleEditState::Normal => {}
+        ObstacleEditState::Drawing(vertices, preview) => {
+            if let Some(id) = preview.take() {
+                commands.entity(id).despawn();
+            }
+            let closing = vertices.len() >= 3 && (vertices[0] - mouse_pos.0).length() < 0.13;
+            // Clicked the first vertex - finish the obstacle
+            if left && closing {
+                let obstacle = Obstacle {
+                    vertices: std::mem::take(vertices),
+                };
+                let i = graph.obstacles.len();
+                history.apply(GraphEdit::AddObstacle { i, obstacle }, &mut graph);
+                *edit_state = ObstacleEditState::Normal;
+            }
+            // Clicked elsewhere - add a vertex
+            else if left {
+                vertices.push(mouse_pos.0);
+            }
+            // Right clicked - cancel the obstacle
+            else if right {
+                *edit_state = ObstacleEditState::Normal;
+            }
+            // Idle - show the outline so far, snapping to the first vertex if it would close the obstacle
+            else {
+                let end = if closing { vertices[0] } else { mouse_pos.0 };
+                *preview = Some(draw_preview(vertices, end, &mut commands));
+            }
+        }
+        &mut ObstacleEditState::DraggingVertex(i, j, from) => {
+            // The vertex may be gone if the obstacle was removed by undo
+            if graph
+                .obstacles
+                .get(i)
+                .is_none_or(|obstacle| j >= obstacle.vertices.len())
+            {
+                *edit_state = ObstacleEditState::Normal;
+            } else if mouse_click.pressed(MouseButton::Left) {
+                graph.obstacles[i].vertices[j] = mouse_pos.0;
+            } else {
+                // Released - record the whole drag as a single edit
+                let to = graph.obstacles[i].vertices[j];
+                if from != to {
+                    history.record(GraphEdit::MoveVertex { i, j, from, to });
+                }
+                *edit_state = ObstacleEditState::Normal;
+            }
+        }
+    }
+    history.commit();
+}
+
+// N to add the navgrid's blocked areas as obstacles, skipping any that were already added
+fn import_navgrid(
+    keys: Res<ButtonInput<KeyCode>>,
+    navgrid: Res<NavGridObstacles>,
+    mut graph: ResMut<FieldGraph>,
+    mut history: ResMut<EditHistory>,
+) {
+    if !keys.just_pressed(KeyCode::KeyN) {
+        return;
+    }
+    let mut added = 0;
+    for obstacle in &navgrid.0 {
+        if !graph.obstacles.contains(obstacle) {
+            let i = graph.obstacles.len();
+            let obstacle = obstacle.clone();
+            history.apply(GraphEdit::AddObstacle { i, obstacle }, &mut graph);
+            added += 1;
+        }
+    }
+    history.commit();
+    eprintln!("Added {added} obstacles from the navgrid");
+}
+
+fn on_exit_obstacle_mode(
+    mut edit_state: ResMut<ObstacleEditState>,
+    graph: Res<FieldGraph>,
+    mut history: ResMut<EditHistory>,
+    mut commands: Commands,
+) {
+    match *edit_state {
+        ObstacleEditState::Drawing(_, Some(id)) => commands.entity(id).despawn(),
+        ObstacleEditState::DraggingVertex(i, j, from) => {
+            // The vertex may be gone if the obstacle was removed by undo
+            let to = graph
+                .obstacles
+                .get(i)
+                .and_then(|obstacle| obstacle.vertices.get(j));
+            if let Some(&to) = to.filter(|&&to| to != from) {
+                history.record(GraphEdit::MoveVertex { i, j, from, to });
+                history.commit();
+            }
+        }
+        _ => {}
+    }
+    *edit_state = ObstacleEditState::Normal;
+}
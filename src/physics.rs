@@ -1,12 +1,51 @@
-use bevy::prelude::*;
+use bevy::{app::FixedMain, prelude::*};
 
+// Rate of the physics and control loop, in Hz, like a roboRIO's
+pub const PHYSICS_RATE: f64 = 50.0;
+// Multiples of real time the simulation can run at
+const SPEEDS: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 3.0, 5.0, 10.0];
+
+/// Runs physics and control on a fixed timestep, in [`FixedUpdate`], so that the same inputs
+/// always give the same motion whatever the frame rate.
 pub struct PhysicsPlugin;
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (apply_velocity, apply_angular_velocity));
+        app.insert_resource(Time::<Fixed>::from_hz(PHYSICS_RATE))
+            .configure_sets(
+                FixedUpdate,
+                (
+                    PhysicsSet::Control,
+                    PhysicsSet::Drivetrain,
+                    PhysicsSet::Integrate,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                FixedUpdate,
+                (apply_velocity, apply_angular_velocity).in_set(PhysicsSet::Integrate),
+            );
     }
 }
 
+/// Keyboard controls to pause, single-step and change the speed of the simulation.
+pub struct TimeControlsPlugin;
+impl Plugin for TimeControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (control_speed, step_physics));
+    }
+}
+
+/// Steps of moving robots each physics step, in order.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PhysicsSet {
+    /// Controllers set the velocities they want.
+    Control,
+    /// Drivetrain models replace those with the velocities the robot can actually reach.
+    Drivetrain,
+    /// Velocities move the robots.
+    Integrate,
+}
+
 #[derive(Component, Clone, Copy)]
 pub struct Velocity(pub Vec2);
 
@@ -28,3 +67,44 @@ fn apply_angular_velocity(mut q: Query<(&AngularVelocity, &mut Transform)>, time
         transform.rotate_z(delta);
     }
 }
+
+// Pauses and resumes the simulation, and changes how fast it runs
+fn control_speed(keys: Res<ButtonInput<KeyCode>>, mut time: ResMut<Time<Virtual>>) {
+    if keys.just_pressed(KeyCode::Space) {
+        if time.is_paused() {
+            time.unpause();
+            eprintln!("Resumed");
+        } else {
+            time.pause();
+            eprintln!("Paused");
+        }
+    }
+
+    let current = SPEEDS
+        .iter()
+        .position(|&speed| speed == time.relative_speed())
+        .unwrap_or(SPEEDS.len() / 2);
+    let new = if keys.just_pressed(KeyCode::Equal) {
+        (current + 1).min(SPEEDS.len() - 1)
+    } else if keys.just_pressed(KeyCode::Minus) {
+        current.saturating_sub(1)
+    } else {
+        return;
+    };
+    time.set_relative_speed(SPEEDS[new]);
+    eprintln!("Simulation speed: {}x", SPEEDS[new]);
+}
+
+// Runs a single physics step while paused
+fn step_physics(world: &mut World) {
+    let keys = world.resource::<ButtonInput<KeyCode>>();
+    if !keys.just_pressed(KeyCode::Period) || !world.resource::<Time<Virtual>>().is_paused() {
+        return;
+    }
+    let fixed = world.resource::<Time<Fixed>>();
+    let mut time = fixed.as_generic();
+    time.advance_by(fixed.timestep());
+    *world.resource_mut::<Time>() = time;
+    world.run_schedule(FixedMain);
+    *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+/// Gains of a [`Pid`] controller.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PidGains {
+    pub p: f32,
+    pub i: f32,
+    pub d: f32,
+    /// Most the integral term can add to the output, so that it doesn't wind up while the error
+    /// can't be corrected any faster.
+    pub max_integral: f32,
+    /// Time constant of the low-pass filter on the derivative of the error, in s. 0 leaves it unfiltered.
+    pub derivative_filter: f32,
+}
+
+/// The state of a PID controller, which turns an error into the output that corrects it.
+/// The gains are kept separately, so that several controllers can share them.
+#[derive(Clone, Default)]
+pub struct Pid {
+    integral: f32,
+    prev_error: Option<f32>,
+    // Filtered rate of change of the error
+    derivative: f32,
+}
+
+impl Pid {
+    /// Forgets the error history, for when the setpoint jumps.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Output to correct `error`, measured `dt` s after the last one.
+    pub fn update(&mut self, gains: &PidGains, error: f32, dt: f32) -> f32 {
+        if gains.i > 0.0 {
+            let limit = gains.max_integral / gains.i;
+            self.integral = (self.integral + error * dt).clamp(-limit, limit);
+        } else {
+            self.integral = 0.0;
+        }
+        if let Some(prev_error) = self.prev_error.filter(|_| dt > 0.0) {
+            let raw = (error - prev_error) / dt;
+            self.derivative += (raw - self.derivative) * dt / (gains.derivative_filter + dt);
+        }
+        self.prev_error = Some(error);
+        gains.p * error + gains.i * self.integral + gains.d * self.derivative
+    }
+}
@@ -0,0 +1,429 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use bevy::{color::palettes::css::*, prelude::*};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use robot_planner::PathConstraints;
+
+use crate::pid::PidGains;
+
+/// Side length of the default robot's square bumpers, in m.
+pub const DEFAULT_BUMPER_SIZE: f32 = 0.61;
+// (fill color, border color) of each robot in the alliance, unless a profile says otherwise
+const BLUE_COLORS: [(Srgba, Srgba); 3] = [
+    (BLUE, DARK_BLUE),
+    (DEEP_SKY_BLUE, STEEL_BLUE),
+    (MEDIUM_PURPLE, INDIGO),
+];
+const RED_COLORS: [(Srgba, Srgba); 3] = [
+    (RED, DARK_RED),
+    (ORANGE_RED, FIRE_BRICK),
+    (HOT_PINK, MEDIUM_VIOLET_RED),
+];
+// Starting position of each robot, unless a profile says otherwise
+const START_POSITIONS: [Vec2; 3] = [
+    Vec2::new(-1.43, -2.67),
+    Vec2::new(-1.43, -1.17),
+    Vec2::new(-1.43, 0.33),
+];
+// Seconds between checks of whether profile files were edited
+const RELOAD_INTERVAL: f32 = 0.5;
+
+pub struct ProfilePlugin;
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, reload_profiles);
+    }
+}
+
+/// Which side of the field the robots play for. It sets the field image and the robots' default colors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum Alliance {
+    #[default]
+    Blue,
+    Red,
+}
+
+impl Alliance {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Blue => "blue",
+            Self::Red => "red",
+        }
+    }
+}
+
+/// Physical and control parameters of a robot, loaded from a JSON file.
+/// Fields missing from the file keep their default values.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RobotProfile {
+    /// Side length of the robot's square bumpers, in m.
+    pub bumper_size: f32,
+    pub drive: DriveProfile,
+    pub turn: TurnProfile,
+    pub drivetrain: Drivetrain,
+    /// Only used when the sim starts.
+    pub start: StartPose,
+    #[serde(with = "hex_color")]
+    pub color: Srgba,
+    #[serde(with = "hex_color")]
+    pub border_color: Srgba,
+}
+
+/// Gains and limits for driving along paths.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DriveProfile {
+    /// Corrects the position error, in m, with a velocity, in m/s.
+    #[serde(flatten)]
+    pub pid: PidGains,
+    /// How much of the planned velocity is commanded directly, on top of the correction.
+    pub velocity_ff: f32,
+    /// m/s
+    pub max_speed: f32,
+    /// m/s²
+    pub max_accel: f32,
+    /// m/s²
+    pub max_centripetal_accel: f32,
+    /// Shortest distance ahead along the path that pure pursuit aims for, in m.
+    pub min_lookahead: f32,
+    /// How far ahead pure pursuit aims per unit of speed, in s. 0 keeps the lookahead fixed.
+    pub lookahead_time: f32,
+}
+
+/// Gains and limits for turning to face the target.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TurnProfile {
+    /// Corrects the heading error, in rad, with an angular velocity, in rad/s.
+    #[serde(flatten)]
+    pub pid: PidGains,
+    /// rad/s
+    pub max_speed: f32,
+}
+
+/// How the robot's wheels move it.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Drivetrain {
+    /// Moves exactly as commanded, changing velocity instantly.
+    Ideal,
+    Swerve(SwerveProfile),
+    /// Can only drive along its heading, turning by driving its sides at different speeds.
+    Differential(DifferentialProfile),
+}
+
+/// Layout and limits of a four-module swerve drivetrain.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwerveProfile {
+    /// Distance between the left and right modules, in m.
+    pub track_width: f32,
+    /// Distance between the front and back modules, in m.
+    pub wheelbase: f32,
+    /// m/s
+    pub max_module_speed: f32,
+    /// rad/s
+    pub max_steer_rate: f32,
+    /// m/s²
+    pub max_wheel_accel: f32,
+}
+
+impl Default for SwerveProfile {
+    fn default() -> Self {
+        Self {
+            track_width: 0.52,
+            wheelbase: 0.52,
+            max_module_speed: 4.5,
+            max_steer_rate: 15.0,
+            max_wheel_accel: 8.0,
+        }
+    }
+}
+
+/// Layout and limits of a differential (tank) drivetrain.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DifferentialProfile {
+    /// Distance between the left and right wheels, in m.
+    pub track_width: f32,
+    /// m/s
+    pub max_wheel_speed: f32,
+    /// m/s²
+    pub max_wheel_accel: f32,
+}
+
+impl DifferentialProfile {
+    /// Fastest the robot can turn in place, in rad/s.
+    pub fn max_turn_rate(&self) -> f32 {
+        2.0 * self.max_wheel_speed / self.track_width
+    }
+}
+
+impl Default for DifferentialProfile {
+    fn default() -> Self {
+        Self {
+            track_width: 0.55,
+            max_wheel_speed: 4.0,
+            max_wheel_accel: 8.0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StartPose {
+    /// m
+    pub x: f32,
+    /// m
+    pub y: f32,
+    /// Degrees counterclockwise from the +x axis.
+    pub heading: f32,
+}
+
+impl RobotProfile {
+    /// The built-in profile of the robot with the given number in the alliance.
+    pub fn default_for(alliance: Alliance, robot: usize) -> Self {
+        let (color, border_color) = match alliance {
+            Alliance::Blue => BLUE_COLORS[robot],
+            Alliance::Red => RED_COLORS[robot],
+        };
+        let start = START_POSITIONS[robot];
+        Self {
+            bumper_size: DEFAULT_BUMPER_SIZE,
+            drive: DriveProfile {
+                pid: PidGains {
+                    p: 5.0,
+                    i: 0.0,
+                    d: 0.0,
+                    max_integral: 0.5,
+                    derivative_filter: 0.02,
+                },
+                velocity_ff: 1.0,
+                max_speed: 4.0,
+                max_accel: 3.0,
+                max_centripetal_accel: 3.0,
+                min_lookahead: 0.4,
+                lookahead_time: 0.3,
+            },
+            turn: TurnProfile {
+                pid: PidGains {
+                    p: 5.0,
+                    i: 0.0,
+                    d: 0.0,
+                    max_integral: 0.5,
+                    derivative_filter: 0.02,
+                },
+                max_speed: 4.0,
+            },
+            drivetrain: Drivetrain::Ideal,
+            start: StartPose {
+                x: start.x,
+                y: start.y,
+                heading: 90.0,
+            },
+            color,
+            border_color,
+        }
+    }
+
+    /// Speed and acceleration limits for the robot's paths exported to PathPlanner.
+    pub fn path_constraints(&self) -> PathConstraints {
+        PathConstraints {
+            max_velocity: self.drive.max_speed,
+            max_acceleration: self.drive.max_accel,
+            max_angular_velocity: self.turn.max_speed.to_degrees(),
+            ..Default::default()
+        }
+    }
+
+    /// Reads a profile file on top of `base`, which supplies any fields the file leaves out.
+    pub fn load(path: &Path, base: &RobotProfile) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read robot profile {}: {e}", path.display()))?;
+        let overlay: Value = serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid robot profile {}: {e}", path.display()))?;
+        let mut merged = serde_json::to_value(base).unwrap();
+        merge(&mut merged, overlay);
+        let profile: Self = serde_json::from_value(merged)
+            .map_err(|e| format!("Invalid robot profile {}: {e}", path.display()))?;
+        profile
+            .check()
+            .map_err(|e| format!("Invalid robot profile {}: {e}", path.display()))?;
+        Ok(profile)
+    }
+
+    // Limits and sizes the sim divides by or inflates obstacles with have to be positive
+    fn check(&self) -> Result<(), String> {
+        let mut fields = vec![
+            ("bumper_size", self.bumper_size),
+            ("drive.max_speed", self.drive.max_speed),
+            ("drive.max_accel", self.drive.max_accel),
+            (
+                "drive.max_centripetal_accel",
+                self.drive.max_centripetal_accel,
+            ),
+            ("drive.min_lookahead", self.drive.min_lookahead),
+            ("turn.max_speed", self.turn.max_speed),
+        ];
+        match &self.drivetrain {
+            Drivetrain::Ideal => {}
+            Drivetrain::Swerve(swerve) => fields.extend([
+                ("drivetrain.track_width", swerve.track_width),
+                ("drivetrain.wheelbase", swerve.wheelbase),
+                ("drivetrain.max_module_speed", swerve.max_module_speed),
+                ("drivetrain.max_steer_rate", swerve.max_steer_rate),
+                ("drivetrain.max_wheel_accel", swerve.max_wheel_accel),
+            ]),
+            Drivetrain::Differential(differential) => fields.extend([
+                ("drivetrain.track_width", differential.track_width),
+                ("drivetrain.max_wheel_speed", differential.max_wheel_speed),
+                ("drivetrain.max_wheel_accel", differential.max_wheel_accel),
+            ]),
+        }
+        match fields.into_iter().find(|&(_, value)| value <= 0.0) {
+            Some((field, value)) => Err(format!("{field} must be positive, but is {value}")),
+            None if self.drive.lookahead_time < 0.0 => Err(format!(
+                "drive.lookahead_time can't be negative, but is {}",
+                self.drive.lookahead_time
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+// Overwrites the fields of `base` with those present in `overlay`, going into nested objects
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(field) => merge(field, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// A profile along with the file it came from, if any.
+pub struct LoadedProfile {
+    pub profile: RobotProfile,
+    // Built-in profile the file is read on top of
+    base: RobotProfile,
+    path: Option<PathBuf>,
+    // When the file was last modified, to tell when it needs to be reloaded
+    modified: Option<SystemTime>,
+}
+
+/// The profile of each robot in the alliance, indexed by robot number.
+#[derive(Resource)]
+pub struct RobotProfiles(pub Vec<LoadedProfile>);
+
+impl RobotProfiles {
+    /// Loads the given profile files for the first robots, and uses the built-in profiles for the rest.
+    pub fn load(paths: &[String], alliance: Alliance) -> Result<Self, String> {
+        if paths.len() > START_POSITIONS.len() {
+            return Err(format!(
+                "Got {} robot profiles, but there are only {} robots",
+                paths.len(),
+                START_POSITIONS.len()
+            ));
+        }
+        let profiles = (0..START_POSITIONS.len())
+            .map(|robot| {
+                let base = RobotProfile::default_for(alliance, robot);
+                let Some(path) = paths.get(robot) else {
+                    return Ok(LoadedProfile {
+                        profile: base.clone(),
+                        base,
+                        path: None,
+                        modified: None,
+                    });
+                };
+                let path = PathBuf::from(path);
+                Ok(LoadedProfile {
+                    profile: RobotProfile::load(&path, &base)?,
+                    base,
+                    modified: modified_time(&path),
+                    path: Some(path),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self(profiles))
+    }
+
+    /// The bumper size of the biggest robot, in m.
+    pub fn max_bumper_size(&self) -> f32 {
+        self.0
+            .iter()
+            .map(|loaded| loaded.profile.bumper_size)
+            .fold(0.0, f32::max)
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Reloads profile files that were edited since they were last loaded.
+// A file that fails to load is reported, and the robot keeps its previous profile.
+fn reload_profiles(
+    mut profiles: ResMut<RobotProfiles>,
+    time: Res<Time>,
+    mut since_check: Local<f32>,
+) {
+    *since_check += time.delta_seconds();
+    if *since_check < RELOAD_INTERVAL {
+        return;
+    }
+    *since_check = 0.0;
+
+    // Only flag the profiles as changed when one was actually reloaded
+    let mut reloaded = false;
+    for (robot, loaded) in profiles.bypass_change_detection().0.iter_mut().enumerate() {
+        let Some(path) = &loaded.path else {
+            continue;
+        };
+        let modified = modified_time(path);
+        if modified == loaded.modified {
+            continue;
+        }
+        loaded.modified = modified;
+        match RobotProfile::load(path, &loaded.base) {
+            Ok(profile) => {
+                eprintln!(
+                    "Reloaded robot {} profile from {}",
+                    robot + 1,
+                    path.display()
+                );
+                loaded.profile = profile;
+                reloaded = true;
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+    if reloaded {
+        profiles.set_changed();
+    }
+}
+
+// Colors are stored as hex strings, read and written the same way as tag colors in graph files
+mod hex_color {
+    use bevy::color::{ColorToPacked, Srgba};
+    use robot_planner::Color;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Srgba, serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = color.to_u8_array();
+        Color { r, g, b, a }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Srgba, D::Error> {
+        let Color { r, g, b, a } = Color::deserialize(deserializer)?;
+        Ok(Srgba::rgba_u8(r, g, b, a))
+    }
+}
@@ -1,63 +1,384 @@
-use std::f32::consts::PI;
+use std::collections::BTreeSet;
+use std::f32::consts::FRAC_PI_4;
 
 use bevy::{color::palettes::css::*, prelude::*};
 use bevy_prototype_lyon::prelude::*;
 
-use pathfinding::directed::astar::astar;
-
-use crate::graph::{FieldGraph, SpatialGraph};
-use crate::physics::{AngularVelocity, Velocity};
-use crate::{Mode, UNITS_SCALE_FACTOR};
-
-const ROBOT_COLOR: Srgba = BLUE;
-const ROBOT_BORDER_COLOR: Srgba = DARK_BLUE;
+use serde::{Deserialize, Serialize};
+
+use robot_planner::{clearance, compute_path, norm_angle, smooth_path, PathPlannerPath, Waypoint};
+
+use crate::avoidance::{schedule, Avoidance, Reservation, Yield};
+use crate::differential::DifferentialDrive;
+use crate::graph::{world_to_field, FieldGraph};
+use crate::physics::{AngularVelocity, PhysicsSet, Velocity};
+use crate::pid::{Pid, PidGains};
+use crate::profile::{Drivetrain, RobotProfile, RobotProfiles, TurnProfile};
+use crate::swerve::{spawn_module_arrows, SwerveDrive};
+use crate::trajectory::{Trajectory, TrajectoryConstraints};
+use crate::{Mode, SavePath, UNITS_SCALE_FACTOR};
+
+// Border color of the robot that clicks command
+const SELECTED_BORDER_COLOR: Srgba = WHITE;
+const ROBOT_KEYS: [KeyCode; 3] = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3];
+// Gains of the Ramsete controller, which tracks trajectories for robots that can only drive along their heading
+const RAMSETE_B: f32 = 2.0;
+const RAMSETE_ZETA: f32 = 0.7;
+// A robot counts as having arrived once it's this close to the end of its path, in m, and slower than this, in m/s
+const ARRIVAL_DISTANCE: f32 = 0.1;
+const ARRIVAL_SPEED: f32 = 0.1;
+// Pure pursuit slows to at most this times the distance left to the end of the path, in m/s per m
+const PURSUIT_STOP_GAIN: f32 = 5.0;
 
 pub struct RobotPlugin;
 impl Plugin for RobotPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_robot)
+        app.add_systems(Startup, spawn_robots)
+            .insert_resource(PathSmoothing(false))
+            .insert_resource(SelectedRobot(0))
             .add_event::<RecomputeRobotPath>()
             .add_systems(
                 PostStartup,
-                |mut writer: EventWriter<RecomputeRobotPath>| {
-                    writer.send_default();
+                |q: Query<Entity, With<Robot>>, mut writer: EventWriter<RecomputeRobotPath>| {
+                    writer.send_batch(q.iter().map(RecomputeRobotPath));
                 },
             )
             .add_systems(
-                Update,
+                FixedUpdate,
                 (
-                    follow_path,
-                    recompute_robot_path,
-                    face_target,
-                    mouse_interaction.run_if(in_state(Mode::Normal)),
+                    (follow_path, face_target)
+                        .chain()
+                        .in_set(PhysicsSet::Control),
+                    recompute_robot_path.before(PhysicsSet::Control),
                 ),
+            )
+            .add_systems(
+                Update,
+                (apply_profiles, move_trajectory_marker, move_yield_marker),
             );
     }
 }
 
+/// Keyboard and mouse controls for the robots.
+pub struct RobotControlsPlugin;
+impl Plugin for RobotControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                (select_robot, mouse_interaction)
+                    .chain()
+                    .run_if(in_state(Mode::Normal)),
+                highlight_selected_robot,
+                toggle_smoothing,
+                toggle_follow_mode,
+                export_path,
+            ),
+        );
+    }
+}
+
+/// A robot in the alliance, numbered from 0.
+#[derive(Component)]
+pub struct Robot(pub usize);
+
+/// The robot that mouse clicks command.
+#[derive(Resource)]
+pub struct SelectedRobot(pub usize);
+
+// The robot's bumpers, whose border shows whether it's selected
 #[derive(Component)]
-pub struct Robot;
+struct RobotBody {
+    robot: usize,
+    border: Srgba,
+}
+
+/// How the robot drives along its planned path.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FollowMode {
+    /// Head straight for each waypoint in turn.
+    Waypoints,
+    /// Track a time-parameterized profile of the path that respects acceleration limits.
+    Trajectory,
+    /// Chase a point a lookahead distance further along the path than the robot.
+    PurePursuit,
+}
+
+// How closely the robot kept to its path, reported when it arrives
+struct TrackingStats {
+    error_sum: f32,
+    max_error: f32,
+    samples: u32,
+    // m driven so far
+    distance: f32,
+}
+
+/// How a robot's last drive along a path went.
+#[derive(Clone)]
+pub struct RunReport {
+    /// s from setting off to arriving
+    pub time: f32,
+    /// m driven
+    pub distance: f32,
+    /// Average distance from the path, in m.
+    pub mean_error: f32,
+    /// m
+    pub max_error: f32,
+    pub path: Vec<Waypoint>,
+}
 
 #[derive(Component)]
 pub struct PathFollower {
-    target_path: Vec<Vec2>,
-    p: f32,
+    target_path: Vec<Waypoint>,
+    trajectory: Option<Trajectory>,
+    // s since the robot started on the path
+    elapsed: f32,
+    pub mode: FollowMode,
+    // Index of the waypoint the robot is heading for when following waypoints
+    next_waypoint: usize,
+    // Index of the segment the robot was last closest to when following with pure pursuit
+    pursuit_segment: usize,
+    // The point pure pursuit is chasing, and the direction to it from the robot
+    lookahead_point: Option<(Vec2, f32)>,
+    // Until the robot arrives
+    tracking: Option<TrackingStats>,
+    /// Set once the robot arrives at the end of its path.
+    pub last_run: Option<RunReport>,
+    // Where the robot is waiting for another one to pass, if anywhere
+    yielding: Option<Yield>,
+    /// Gains of the controllers that correct the robot's position along each axis.
+    pub drive_gains: PidGains,
+    translation: [Pid; 2],
+    /// How much of the planned velocity is commanded directly.
+    pub velocity_ff: f32,
     max_speed: f32,
+    max_accel: f32,
+    max_centripetal_accel: f32,
+    min_lookahead: f32,
+    lookahead_time: f32,
+    // How fast the robot turns in place, if it can only drive along its heading, in rad/s
+    turn_rate: Option<f32>,
+}
+
+impl PathFollower {
+    fn new(profile: &RobotProfile) -> Self {
+        let mut follower = Self {
+            target_path: Vec::new(),
+            trajectory: None,
+            elapsed: 0.0,
+            mode: FollowMode::Trajectory,
+            next_waypoint: 0,
+            pursuit_segment: 0,
+            lookahead_point: None,
+            tracking: None,
+            last_run: None,
+            yielding: None,
+            drive_gains: profile.drive.pid.clone(),
+            translation: Default::default(),
+            velocity_ff: 0.0,
+            max_speed: 0.0,
+            max_accel: 0.0,
+            max_centripetal_accel: 0.0,
+            min_lookahead: 0.0,
+            lookahead_time: 0.0,
+            turn_rate: None,
+        };
+        follower.set_profile(profile);
+        follower
+    }
+
+    fn set_profile(&mut self, profile: &RobotProfile) {
+        let drive = &profile.drive;
+        self.drive_gains = drive.pid.clone();
+        self.velocity_ff = drive.velocity_ff;
+        self.max_speed = drive.max_speed;
+        self.max_accel = drive.max_accel;
+        self.max_centripetal_accel = drive.max_centripetal_accel;
+        self.min_lookahead = drive.min_lookahead;
+        self.lookahead_time = drive.lookahead_time;
+        self.turn_rate = match &profile.drivetrain {
+            Drivetrain::Differential(differential) => {
+                Some(profile.turn.max_speed.min(differential.max_turn_rate()))
+            }
+            _ => None,
+        };
+    }
+
+    fn constraints(&self) -> TrajectoryConstraints {
+        TrajectoryConstraints {
+            max_velocity: self.max_speed,
+            max_acceleration: self.max_accel,
+            max_centripetal_acceleration: self.max_centripetal_accel,
+            max_turn_rate: self.turn_rate,
+        }
+    }
+
+    // Velocity to correct a position error with
+    fn correct(&mut self, error: Vec2, dt: f32) -> Vec2 {
+        let [x, y] = &mut self.translation;
+        Vec2::new(
+            x.update(&self.drive_gains, error.x, dt),
+            y.update(&self.drive_gains, error.y, dt),
+        )
+    }
+
+    // Starts the robot along a newly planned path
+    fn start_path(&mut self, path: Vec<Waypoint>, trajectory: Trajectory) {
+        self.tracking = (path.len() > 1).then_some(TrackingStats {
+            error_sum: 0.0,
+            max_error: 0.0,
+            samples: 0,
+            distance: 0.0,
+        });
+        self.last_run = None;
+        self.target_path = path;
+        self.trajectory = Some(trajectory);
+        self.elapsed = 0.0;
+        self.next_waypoint = 0;
+        self.pursuit_segment = 0;
+        self.translation = Default::default();
+        self.lookahead_point = None;
+    }
+
+    // Notes how far the robot is from its path, and reports how it did once it arrives
+    fn track(&mut self, robot: usize, pos: Vec2, vel: Vec2, dt: f32) {
+        let Some(stats) = &mut self.tracking else {
+            return;
+        };
+        let error = self
+            .target_path
+            .windows(2)
+            .map(|w| closest_on_segment(w[0].pos, w[1].pos, pos).distance(pos))
+            .fold(f32::INFINITY, f32::min);
+        stats.error_sum += error;
+        stats.max_error = stats.max_error.max(error);
+        stats.samples += 1;
+        stats.distance += vel.length() * dt;
+
+        let end = self.target_path.last().map_or(pos, |wp| wp.pos);
+        if pos.distance(end) < ARRIVAL_DISTANCE && vel.length() < ARRIVAL_SPEED {
+            let report = RunReport {
+                time: self.elapsed,
+                distance: stats.distance,
+                mean_error: stats.error_sum / stats.samples as f32,
+                max_error: stats.max_error,
+                path: self.target_path.clone(),
+            };
+            eprintln!(
+                "Robot {} ({:?}): Arrived after {:.2} s, tracking error mean {:.3} m, max {:.3} m",
+                robot + 1,
+                self.mode,
+                report.time,
+                report.mean_error,
+                report.max_error
+            );
+            self.tracking = None;
+            self.last_run = Some(report);
+        }
+    }
+}
+
+fn closest_on_segment(a: Vec2, b: Vec2, p: Vec2) -> Vec2 {
+    let ab = b - a;
+    let len_sq = ab.length_squared();
+    if len_sq < 1e-8 {
+        return a;
+    }
+    a + ab * ((p - a).dot(ab) / len_sq).clamp(0.0, 1.0)
 }
 
 #[derive(Component)]
 pub struct TargetFacer {
-    p: f32,
+    pub gains: PidGains,
+    pid: Pid,
     max_speed: f32,
 }
 
+impl TargetFacer {
+    fn new(profile: &TurnProfile) -> Self {
+        Self {
+            gains: profile.pid.clone(),
+            pid: Pid::default(),
+            max_speed: profile.max_speed,
+        }
+    }
+
+    /// Angular velocity to turn from `cur_angle` toward `target_angle`.
+    fn turn_toward(&mut self, target_angle: f32, cur_angle: f32, dt: f32) -> f32 {
+        let diff = norm_angle(target_angle - cur_angle);
+        self.pid
+            .update(&self.gains, diff, dt)
+            .clamp(-self.max_speed, self.max_speed)
+    }
+}
+
+/// Where the robot is headed.
 #[derive(Component)]
-pub struct TargetPosition(Vec2);
+pub struct TargetPosition(pub Vec2);
 
-fn spawn_robot(mut commands: Commands) {
+/// Shows where a robot's trajectory says it should be, and which way it should be going,
+/// or the point pure pursuit is chasing.
+#[derive(Component)]
+struct TrajectoryMarker(Entity);
+
+/// Shows where a robot is waiting to let another one through.
+#[derive(Component)]
+struct YieldMarker(Entity);
+
+fn spawn_robots(
+    profiles: Res<RobotProfiles>,
+    selected: Res<SelectedRobot>,
+    mut commands: Commands,
+) {
+    for (i, loaded) in profiles.0.iter().enumerate() {
+        let profile = &loaded.profile;
+        let follower = PathFollower::new(profile);
+        let facer = TargetFacer::new(&profile.turn);
+        let init_pos = Vec2::new(profile.start.x, profile.start.y);
+        let init_rot = profile.start.heading.to_radians();
+        let robot = commands
+            .spawn((Robot(i), TargetPosition(init_pos), follower, facer))
+            .insert(SpatialBundle {
+                transform: Transform::from_xyz(init_pos.x, init_pos.y, 0.0)
+                    .with_rotation(Quat::from_rotation_z(init_rot)),
+                ..Default::default()
+            })
+            .insert(Velocity(Vec2::new(0.0, 0.0)))
+            .insert(AngularVelocity(0.0))
+            .id();
+        insert_drivetrain(robot, &profile.drivetrain, &mut commands);
+        spawn_robot_shapes(robot, i, profile, i == selected.0, &mut commands);
+    }
+}
+
+// Gives the robot the model of its drivetrain, replacing any previous one
+fn insert_drivetrain(robot: Entity, drivetrain: &Drivetrain, commands: &mut Commands) {
+    let mut robot = commands.entity(robot);
+    robot.remove::<(SwerveDrive, DifferentialDrive)>();
+    match drivetrain {
+        Drivetrain::Ideal => {}
+        Drivetrain::Swerve(swerve) => {
+            robot.insert(SwerveDrive::new(swerve.clone()));
+        }
+        Drivetrain::Differential(differential) => {
+            robot.insert(DifferentialDrive::new(differential.clone()));
+        }
+    }
+}
+
+// Draws the robot and its markers with the size and colors from its profile
+fn spawn_robot_shapes(
+    robot: Entity,
+    index: usize,
+    profile: &RobotProfile,
+    selected: bool,
+    commands: &mut Commands,
+) {
+    let (color, border) = (profile.color, profile.border_color);
     let shapes = {
         let rect_shape = shapes::Rectangle {
-            extents: Vec2::new(0.61, 0.61),
+            extents: Vec2::splat(profile.bumper_size),
             ..Default::default()
         };
         let rect = commands
@@ -66,8 +387,19 @@ fn spawn_robot(mut commands: Commands) {
                     path: GeometryBuilder::build_as(&rect_shape),
                     ..Default::default()
                 },
-                Fill::color(ROBOT_COLOR),
-                Stroke::new(ROBOT_BORDER_COLOR, 10.0 / UNITS_SCALE_FACTOR),
+                Fill::color(color),
+                Stroke::new(
+                    if selected {
+                        SELECTED_BORDER_COLOR
+                    } else {
+                        border
+                    },
+                    10.0 / UNITS_SCALE_FACTOR,
+                ),
+                RobotBody {
+                    robot: index,
+                    border,
+                },
             ))
             .insert(Transform::from_xyz(0.0, 0.0, 1.0))
             .id();
@@ -84,163 +416,582 @@ fn spawn_robot(mut commands: Commands) {
             .id();
         [rect, line]
     };
+    commands.entity(robot).push_children(&shapes);
+    if let Drivetrain::Swerve(_) = profile.drivetrain {
+        let arrows = spawn_module_arrows(commands);
+        commands.entity(robot).push_children(&arrows);
+    }
 
-    let follower = PathFollower {
-        target_path: Vec::new(),
-        // Movement values
-        p: 5.0,
-        // m/s
-        max_speed: 4.0,
+    let marker_shape = shapes::Circle {
+        radius: 0.08,
+        center: Vec2::ZERO,
     };
-    let facer = TargetFacer {
-        // Rotation values
-        p: 5.0,
-        // rad/s
-        max_speed: 4.0,
-    };
-    let init_pos = Vec2::new(-1.43, -2.67);
-    let init_rot = PI / 2.0;
+    let heading_shape = shapes::Line(Vec2::ZERO, Vec2::new(0.2, 0.0));
+    let heading = commands
+        .spawn((
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&heading_shape),
+                ..Default::default()
+            },
+            Stroke::new(border, 6.0 / UNITS_SCALE_FACTOR),
+        ))
+        .id();
     commands
-        .spawn((Robot, TargetPosition(init_pos), follower, facer))
-        .push_children(&shapes)
-        .insert(SpatialBundle {
-            transform: Transform::from_xyz(init_pos.x, init_pos.y, 0.0)
-                .with_rotation(Quat::from_rotation_z(init_rot)),
+        .spawn((
+            TrajectoryMarker(robot),
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&marker_shape),
+                spatial: SpatialBundle {
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Stroke::new(border, 6.0 / UNITS_SCALE_FACTOR),
+        ))
+        .add_child(heading);
+
+    let yield_shape = shapes::Circle {
+        radius: clearance(profile.bumper_size),
+        center: Vec2::ZERO,
+    };
+    commands.spawn((
+        YieldMarker(robot),
+        ShapeBundle {
+            path: GeometryBuilder::build_as(&yield_shape),
+            spatial: SpatialBundle {
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
             ..Default::default()
-        })
-        .insert(Velocity(Vec2::new(0.0, 0.0)))
-        .insert(AngularVelocity(0.0));
+        },
+        Stroke::new(color, 8.0 / UNITS_SCALE_FACTOR),
+    ));
 }
 
-fn follow_path(mut q: Query<(&mut PathFollower, &mut Velocity, &Transform)>) {
-    let (mut follower, mut vel, transform) = q.single_mut();
-    let pos = transform.translation.truncate();
+// Applies reloaded profiles to the robots on the field, except for their starting poses
+#[allow(clippy::too_many_arguments)]
+fn apply_profiles(
+    profiles: Res<RobotProfiles>,
+    selected: Res<SelectedRobot>,
+    mut robot_q: Query<(Entity, &Robot, &mut PathFollower, &mut TargetFacer)>,
+    trajectory_markers: Query<Entity, With<TrajectoryMarker>>,
+    yield_markers: Query<Entity, With<YieldMarker>>,
+    mut writer: EventWriter<RecomputeRobotPath>,
+    mut commands: Commands,
+) {
+    if !profiles.is_changed() || profiles.is_added() {
+        return;
+    }
+    for id in trajectory_markers.iter().chain(&yield_markers) {
+        commands.entity(id).despawn_recursive();
+    }
+    for (id, robot, mut follower, mut facer) in &mut robot_q {
+        let profile = &profiles.0[robot.0].profile;
+        follower.set_profile(profile);
+        *facer = TargetFacer::new(&profile.turn);
+        insert_drivetrain(id, &profile.drivetrain, &mut commands);
+        commands.entity(id).despawn_descendants();
+        spawn_robot_shapes(id, robot.0, profile, robot.0 == selected.0, &mut commands);
+        writer.send(RecomputeRobotPath(id));
+    }
+}
 
-    let passthrough = |i| if i == 0 { 0.1 } else { 0.5 };
+fn follow_path(
+    mut q: Query<(
+        &Robot,
+        &mut PathFollower,
+        &mut TargetFacer,
+        &mut Velocity,
+        &mut AngularVelocity,
+        &Transform,
+    )>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    for (robot, mut follower, mut facer, mut vel, mut avel, transform) in &mut q {
+        let pos = transform.translation.truncate();
+        let heading = transform.rotation.to_euler(EulerRot::ZYX).0;
+        let speed = vel.0.length();
+        follower.elapsed += dt;
+        if follower.mode == FollowMode::PurePursuit {
+            let target = pursuit_target(&mut follower, pos, speed, dt);
+            follower.lookahead_point = target.map(|(point, _)| (point, (point - pos).to_angle()));
+            (vel.0, avel.0) = match target {
+                None => (Vec2::ZERO, 0.0),
+                Some((point, speed)) if follower.turn_rate.is_none() => {
+                    ((point - pos).normalize_or_zero() * speed, avel.0)
+                }
+                Some((point, speed)) => {
+                    // Drive the arc through the point, turning in place first if it's well off to the side
+                    let local = Vec2::from_angle(-heading).rotate(point - pos);
+                    if local.length() < 1e-3 {
+                        (Vec2::ZERO, 0.0)
+                    } else if local.to_angle().abs() > FRAC_PI_4 {
+                        (
+                            Vec2::ZERO,
+                            facer.turn_toward(local.to_angle() + heading, heading, dt),
+                        )
+                    } else {
+                        let curvature = 2.0 * local.y / local.length_squared();
+                        (Vec2::from_angle(heading) * speed, speed * curvature)
+                    }
+                }
+            };
+        } else if follower.turn_rate.is_none() {
+            vel.0 = follow(&mut follower, pos, dt);
+        } else if follower.mode == FollowMode::Trajectory {
+            (vel.0, avel.0) = follow_ramsete(&mut follower, pos, heading);
+        } else {
+            // Turn toward wherever the robot is asked to go, since it can only drive along its heading
+            vel.0 = follow(&mut follower, pos, dt);
+            avel.0 = if vel.0.length() > 1e-3 {
+                facer.turn_toward(vel.0.to_angle(), heading, dt)
+            } else {
+                0.0
+            };
+        }
+        follower.track(robot.0, pos, vel.0, dt);
+    }
+}
+
+// Velocity to drive along the path at
+fn follow(follower: &mut PathFollower, pos: Vec2, dt: f32) -> Vec2 {
+    if follower.mode == FollowMode::Trajectory {
+        let Some(trajectory) = &follower.trajectory else {
+            return Vec2::ZERO;
+        };
+        // Drive at the planned velocity, correcting for any drift from the planned position.
+        // It's averaged over the frame, so that the distance covered matches the plan.
+        let state = trajectory.sample(follower.elapsed);
+        let feedforward = state.velocity + state.acceleration * dt / 2.0;
+        let correction = follower.correct(state.pos - pos, dt);
+        let mut new_vel = follower.velocity_ff * feedforward + correction;
+        if new_vel.length() > follower.max_speed {
+            new_vel = follower.max_speed * new_vel.normalize();
+        }
+        return new_vel;
+    }
 
-    let mut path_iter = follower.target_path.iter().copied().rev().enumerate().rev();
-    let next_wp = path_iter.find(|&(i, wp)| (pos - wp).length() > passthrough(i));
-    follower.target_path = path_iter.map(|t| t.1).collect();
+    let path = &follower.target_path;
+    let last = path.len().saturating_sub(1);
+    let passthrough = |i| if i == last { 0.1 } else { 0.5 };
 
-    let Some((_, next_wp)) = next_wp else {
-        vel.0 = Vec2::ZERO;
-        return;
+    let Some(next) = (follower.next_waypoint..path.len())
+        .find(|&i| (pos - path[i].pos).length() > passthrough(i))
+    else {
+        return Vec2::ZERO;
     };
-    follower.target_path.insert(0, next_wp);
+    let next_wp = path[next];
+    // Heading for a new waypoint is a jump in the setpoint
+    if next != follower.next_waypoint {
+        follower.next_waypoint = next;
+        follower.translation = Default::default();
+    }
+
+    let max_speed = next_wp
+        .max_speed
+        .map_or(follower.max_speed, |s| s.min(follower.max_speed));
+    let mut new_vel = follower.correct(next_wp.pos - pos, dt);
+    if new_vel.length() > max_speed {
+        new_vel = max_speed * new_vel.normalize();
+    }
+    new_vel
+}
+
+// Velocity and angular velocity to track the trajectory at, for a robot that can only drive along its heading
+fn follow_ramsete(follower: &mut PathFollower, pos: Vec2, heading: f32) -> (Vec2, f32) {
+    let Some(trajectory) = &follower.trajectory else {
+        return (Vec2::ZERO, 0.0);
+    };
+    let state = trajectory.sample(follower.elapsed);
+    let (speed, angular_velocity) = (state.velocity.length(), state.angular_velocity);
+
+    // Errors in the robot's frame
+    let error = Vec2::from_angle(-heading).rotate(state.pos - pos);
+    let heading_error = norm_angle(state.heading - heading);
+    let sinc = if heading_error.abs() < 1e-4 {
+        1.0
+    } else {
+        heading_error.sin() / heading_error
+    };
+
+    let k = 2.0 * RAMSETE_ZETA * (angular_velocity.powi(2) + RAMSETE_B * speed.powi(2)).sqrt();
+    let forward =
+        (speed * heading_error.cos() + k * error.x).clamp(-follower.max_speed, follower.max_speed);
+    let turn = angular_velocity + k * heading_error + RAMSETE_B * speed * sinc * error.y;
+    (Vec2::from_angle(heading) * forward, turn)
+}
+
+// The point a lookahead distance further along the path than the robot, and the speed to chase it at
+fn pursuit_target(
+    follower: &mut PathFollower,
+    pos: Vec2,
+    speed: f32,
+    dt: f32,
+) -> Option<(Vec2, f32)> {
+    let path = &follower.target_path;
+    // Never go back to an earlier segment, so that the robot doesn't turn around where the path passes near itself
+    let (segment, closest) = (follower.pursuit_segment..path.len().saturating_sub(1))
+        .map(|i| (i, closest_on_segment(path[i].pos, path[i + 1].pos, pos)))
+        .min_by(|a, b| a.1.distance(pos).total_cmp(&b.1.distance(pos)))?;
+    follower.pursuit_segment = segment;
+
+    // Walk the lookahead distance along the path, stopping at its end
+    let lookahead = follower.min_lookahead.max(follower.lookahead_time * speed);
+    let (mut point, mut left) = (closest, lookahead);
+    for wp in &path[segment + 1..] {
+        let step = point.distance(wp.pos);
+        if step > left {
+            point += (wp.pos - point).normalize() * left;
+            break;
+        }
+        left -= step;
+        point = wp.pos;
+    }
+
+    // Speed up within the acceleration limit, and slow down in time to stop at the end
+    let end = path[path.len() - 1].pos;
+    let remaining = closest.distance(path[segment + 1].pos)
+        + path[segment + 1..]
+            .windows(2)
+            .map(|w| w[0].pos.distance(w[1].pos))
+            .sum::<f32>();
+    let limit = path[segment + 1]
+        .max_speed
+        .map_or(follower.max_speed, |s| s.min(follower.max_speed));
+    let speed = limit
+        .min(speed + follower.max_accel * dt)
+        .min((2.0 * follower.max_accel * remaining).sqrt())
+        .min(PURSUIT_STOP_GAIN * pos.distance(end));
+    Some((point, speed))
+}
 
-    let mut new_vel = follower.p * (next_wp - pos);
-    if new_vel.length() > follower.max_speed {
-        new_vel = follower.max_speed * new_vel.normalize();
+fn move_trajectory_marker(
+    follower_q: Query<&PathFollower>,
+    mut marker_q: Query<(&TrajectoryMarker, &mut Transform, &mut Visibility)>,
+) {
+    for (marker, mut transform, mut visibility) in &mut marker_q {
+        let target = follower_q
+            .get(marker.0)
+            .ok()
+            .and_then(|follower| match follower.mode {
+                FollowMode::Waypoints => None,
+                FollowMode::Trajectory => {
+                    let state = follower.trajectory.as_ref()?.sample(follower.elapsed);
+                    Some((state.pos, state.heading))
+                }
+                FollowMode::PurePursuit => follower.lookahead_point,
+            });
+        let Some((pos, heading)) = target else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *transform =
+            Transform::from_xyz(pos.x, pos.y, 1.2).with_rotation(Quat::from_rotation_z(heading));
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn move_yield_marker(
+    follower_q: Query<&PathFollower>,
+    mut marker_q: Query<(&YieldMarker, &mut Transform, &mut Visibility)>,
+) {
+    for (marker, mut transform, mut visibility) in &mut marker_q {
+        // Shown until the robot sets off again
+        let Some(yielded) = follower_q
+            .get(marker.0)
+            .ok()
+            .filter(|follower| follower.mode == FollowMode::Trajectory)
+            .and_then(|follower| follower.yielding.filter(|y| follower.elapsed < y.until))
+        else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *transform = Transform::from_xyz(yielded.pos.x, yielded.pos.y, 1.2);
+        *visibility = Visibility::Inherited;
     }
-    vel.0 = new_vel;
 }
 
 fn face_target(
     mut q: Query<(
         &mut AngularVelocity,
         &Transform,
-        &TargetFacer,
+        &mut TargetFacer,
         &TargetPosition,
+        &PathFollower,
     )>,
+    time: Res<Time>,
 ) {
-    let (mut avel, transform, facer, target) = q.single_mut();
-
-    fn norm_angle(a: f32) -> f32 {
-        (a + PI).rem_euclid(2.0 * PI) - PI
+    for (mut avel, transform, mut facer, target, follower) in &mut q {
+        // Robots that can only drive along their heading are turned by their path following instead
+        if follower.turn_rate.is_some() {
+            continue;
+        }
+        let pos_diff = target.0 - transform.translation.truncate();
+        if pos_diff.length() < 0.1 {
+            avel.0 = 0.0;
+            facer.pid.reset();
+            continue;
+        }
+        let (axis, axis_angle) = transform.rotation.to_axis_angle();
+        let cur_angle = axis.dot(Vec3::Z) * axis_angle;
+        avel.0 = facer.turn_toward(pos_diff.to_angle(), cur_angle, time.delta_seconds());
     }
+}
 
-    let pos_diff = target.0 - transform.translation.truncate();
-    if pos_diff.length() < 0.1 {
-        avel.0 = 0.0;
-        return;
-    }
-    let target_angle = pos_diff.to_angle();
+/// Asks for the path of the given robot to be planned again.
+#[derive(Event)]
+pub struct RecomputeRobotPath(pub Entity);
 
-    let (axis, axis_angle) = transform.rotation.to_axis_angle();
-    let cur_angle = axis.dot(Vec3::Z) * axis_angle;
+/// Whether planned paths are shortcut and smoothed before the robot follows them.
+#[derive(Resource)]
+pub struct PathSmoothing(pub bool);
 
-    let diff = norm_angle(target_angle - cur_angle);
-    let mut new_avel = facer.p * diff;
-    if new_avel.abs() > facer.max_speed {
-        new_avel = facer.max_speed * new_avel.signum();
+fn toggle_smoothing(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut smoothing: ResMut<PathSmoothing>,
+    q: Query<Entity, With<Robot>>,
+    mut writer: EventWriter<RecomputeRobotPath>,
+) {
+    if keys.just_pressed(KeyCode::KeyP) {
+        smoothing.0 = !smoothing.0;
+        eprintln!(
+            "Path smoothing {}",
+            if smoothing.0 { "enabled" } else { "disabled" }
+        );
+        writer.send_batch(q.iter().map(RecomputeRobotPath));
     }
-    avel.0 = new_avel;
 }
 
-#[derive(Event, Default)]
-struct RecomputeRobotPath;
-
-fn recompute_robot_path(
-    mut q: Query<(&mut PathFollower, &TargetPosition, &Transform), With<Robot>>,
-    graph: Res<FieldGraph>,
-    mut reader: EventReader<RecomputeRobotPath>,
+// Switches how the selected robot follows its path
+fn toggle_follow_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    selected: Res<SelectedRobot>,
+    mut q: Query<(Entity, &Robot, &mut PathFollower)>,
+    mut writer: EventWriter<RecomputeRobotPath>,
 ) {
-    if reader.is_empty() {
+    if !keys.just_pressed(KeyCode::KeyT) {
         return;
     }
-
-    let (mut follower, target, transform) = q.single_mut();
-    if let Some(path) = compute_path(transform.translation.truncate(), target.0, &graph.sg) {
-        follower.target_path = path;
-    } else {
-        eprintln!("No path found");
+    for (id, robot, mut follower) in &mut q {
+        if robot.0 != selected.0 {
+            continue;
+        }
+        follower.mode = match follower.mode {
+            FollowMode::Waypoints => FollowMode::Trajectory,
+            FollowMode::Trajectory => FollowMode::PurePursuit,
+            FollowMode::PurePursuit => FollowMode::Waypoints,
+        };
+        eprintln!("Robot {} following {:?}", robot.0 + 1, follower.mode);
+        writer.send(RecomputeRobotPath(id));
     }
-
-    reader.clear();
 }
 
-fn compute_path(start: Vec2, end: Vec2, graph: &SpatialGraph) -> Option<Vec<Vec2>> {
-    let mut graph = graph.clone();
-    let mut insert_node = |new_node: Vec2| {
-        let closest = graph
-            .nodes
+// Writes the selected robot's path as a PathPlanner path next to the graph file
+fn export_path(
+    keys: Res<ButtonInput<KeyCode>>,
+    selected: Res<SelectedRobot>,
+    q: Query<(&Robot, &PathFollower)>,
+    profiles: Res<RobotProfiles>,
+    save_path: Res<SavePath>,
+) {
+    if !keys.just_pressed(KeyCode::KeyX) {
+        return;
+    }
+    for (robot, follower) in &q {
+        if robot.0 != selected.0 {
+            continue;
+        }
+        let waypoints = follower
+            .target_path
             .iter()
-            .map(|&node| (node - new_node).length())
-            .enumerate()
-            .min_by(|a, b| a.1.total_cmp(&b.1))
-            .map(|(i, _)| i);
-        let new_idx = graph.nodes.len();
-        graph.nodes.push(new_node);
-        if let Some(closest) = closest {
-            graph.edges.push((closest, new_idx));
+            .map(|wp| Waypoint {
+                pos: world_to_field(wp.pos),
+                ..*wp
+            })
+            .collect();
+        let constraints = profiles.0[robot.0].profile.path_constraints();
+        let Ok(path) = PathPlannerPath::new(waypoints, constraints) else {
+            eprintln!("Robot {} has no path to export", robot.0 + 1);
+            continue;
+        };
+        let graph_path = std::path::Path::new(&save_path.0);
+        let stem = graph_path.file_stem().unwrap_or_default().to_string_lossy();
+        let file = graph_path.with_file_name(format!("{stem}-robot{}.path", robot.0 + 1));
+        match path.save(&file) {
+            Ok(()) => eprintln!("Exported robot {} path to {}", robot.0 + 1, file.display()),
+            Err(e) => eprintln!("Couldn't write {}: {e}", file.display()),
         }
-    };
-    insert_node(start);
-    insert_node(end);
+    }
+}
 
-    let end_idx = graph.nodes.len() - 1;
+/// Plans new paths for the robots that asked for one.
+///
+/// Robots take priority in order of their numbers, and each one's trajectory is timed to keep clear
+/// of the ones before it. Whenever a robot gets a new path, all the robots after it are planned again
+/// around it too.
+fn recompute_robot_path(
+    mut q: Query<(
+        Entity,
+        &Robot,
+        &mut PathFollower,
+        &TargetPosition,
+        &Transform,
+        &Velocity,
+    )>,
+    graph: Res<FieldGraph>,
+    smoothing: Res<PathSmoothing>,
+    profiles: Res<RobotProfiles>,
+    mut reader: EventReader<RecomputeRobotPath>,
+) {
+    // Several requests for the same robot in one frame only need one new path
+    let requested: BTreeSet<Entity> = reader.read().map(|event| event.0).collect();
+    let Some(first) = q
+        .iter()
+        .filter(|t| requested.contains(&t.0))
+        .map(|t| t.1 .0)
+        .min()
+    else {
+        return;
+    };
 
-    let dist_cost = |a: Vec2, b: Vec2| ((a - b).length() * 1e5).round() as u32;
+    let mut robots: Vec<_> = q.iter_mut().collect();
+    robots.sort_by_key(|t| t.1 .0);
+    // Trajectories of the robots already planned, which the rest have to keep clear of
+    let mut reserved: Vec<Reservation> = Vec::new();
+    for (id, robot, mut follower, target, transform, vel) in robots {
+        let start = transform.translation.truncate();
+        let heading = transform.rotation.to_euler(EulerRot::ZYX).0;
+        let clearance = clearance(profiles.0[robot.0].profile.bumper_size);
+        // Keeps the robot's current trajectory, or if it has none, keeps it where it is
+        let keep_current = |follower: &PathFollower| {
+            let trajectory = follower.trajectory.clone().unwrap_or_else(|| {
+                let here = Waypoint {
+                    pos: start,
+                    max_speed: None,
+                };
+                Trajectory::generate(&[here], &follower.constraints(), 0.0, heading)
+            });
+            Reservation {
+                robot: robot.0,
+                clearance,
+                trajectory,
+                elapsed: follower.elapsed,
+            }
+        };
+        if robot.0 < first {
+            reserved.push(keep_current(&follower));
+            continue;
+        }
 
-    let successors = |&i: &usize| {
-        let mut succ = Vec::new();
-        for &(a, b) in &graph.edges {
-            if a == i {
-                succ.push(b);
+        let Some(path) = compute_path(
+            start,
+            target.0,
+            &graph.sg,
+            &graph.obstacles,
+            clearance,
+            follower.max_speed,
+            follower.turn_rate,
+            heading,
+        ) else {
+            eprintln!("Robot {}: No path found", robot.0 + 1);
+            reserved.push(keep_current(&follower));
+            continue;
+        };
+        let path = if smoothing.0 {
+            smooth_path(&path, &graph.sg, &graph.obstacles, clearance)
+        } else {
+            path
+        };
+        // Carry on at the current speed, as far as it's along the new path
+        let initial_speed = path
+            .get(1)
+            .map_or(0.0, |wp| vel.0.dot((wp.pos - start).normalize_or_zero()));
+        let (trajectory, avoidance) = schedule(
+            &path,
+            &follower.constraints(),
+            initial_speed,
+            heading,
+            clearance,
+            &reserved,
+        );
+
+        if requested.contains(&id) {
+            eprintln!(
+                "Robot {}: Predicted traversal time: {:.2} s",
+                robot.0 + 1,
+                trajectory.total_time()
+            );
+        }
+        follower.yielding = None;
+        match avoidance {
+            Avoidance::Clear => {}
+            Avoidance::Yielded(yielded) => {
+                eprintln!(
+                    "Robot {} yields to robot {} at ({:.2}, {:.2}) for {:.2} s",
+                    robot.0 + 1,
+                    yielded.to + 1,
+                    yielded.pos.x,
+                    yielded.pos.y,
+                    yielded.wait
+                );
+                follower.yielding = Some(yielded);
             }
-            if b == i {
-                succ.push(a);
+            Avoidance::Unresolved { with } => {
+                eprintln!(
+                    "Robot {}: Can't keep clear of robot {}",
+                    robot.0 + 1,
+                    with + 1
+                );
             }
         }
-        succ.into_iter()
-            .map(|n| (n, dist_cost(graph.nodes[i], graph.nodes[n])))
-            .collect::<Vec<_>>()
-    };
-
-    let heuristic = |&i: &usize| dist_cost(graph.nodes[i], end);
+        reserved.push(Reservation {
+            robot: robot.0,
+            clearance,
+            trajectory: trajectory.clone(),
+            elapsed: 0.0,
+        });
+        follower.start_path(path, trajectory);
+    }
+}
 
-    let path = astar(&(end_idx - 1), successors, heuristic, |&i| i == end_idx);
+fn select_robot(keys: Res<ButtonInput<KeyCode>>, mut selected: ResMut<SelectedRobot>) {
+    for (i, &key) in ROBOT_KEYS.iter().enumerate() {
+        if keys.just_pressed(key) {
+            selected.0 = i;
+            eprintln!("Commanding robot {}", i + 1);
+        }
+    }
+}
 
-    path.map(|t| t.0.into_iter().map(|i| graph.nodes[i]).collect())
+fn highlight_selected_robot(selected: Res<SelectedRobot>, mut q: Query<(&RobotBody, &mut Stroke)>) {
+    if !selected.is_changed() {
+        return;
+    }
+    for (body, mut stroke) in &mut q {
+        stroke.color = if body.robot == selected.0 {
+            SELECTED_BORDER_COLOR
+        } else {
+            body.border
+        }
+        .into();
+    }
 }
 
 fn mouse_interaction(
-    mut robot_q: Query<(&mut Transform, &mut TargetPosition), With<Robot>>,
+    mut robot_q: Query<(Entity, &Robot, &mut Transform, &mut TargetPosition)>,
+    selected: Res<SelectedRobot>,
     mouse_pos: Res<crate::MouseWorldPos>,
     mouse_click: Res<ButtonInput<MouseButton>>,
     mut writer: EventWriter<RecomputeRobotPath>,
 ) {
-    let (mut transform, mut target) = robot_q.single_mut();
+    let Some((id, _, mut transform, mut target)) = robot_q
+        .iter_mut()
+        .find(|(_, robot, _, _)| robot.0 == selected.0)
+    else {
+        return;
+    };
 
     let mut updated = true;
     if mouse_click.just_pressed(MouseButton::Left) {
@@ -254,6 +1005,6 @@ fn mouse_interaction(
     }
 
     if updated {
-        writer.send_default();
+        writer.send(RecomputeRobotPath(id));
     }
 }
@@ -0,0 +1,168 @@
+use bevy::{color::palettes::css::*, prelude::*};
+use bevy_prototype_lyon::prelude::*;
+
+use robot_planner::norm_angle;
+
+use crate::physics::{AngularVelocity, PhysicsSet, Velocity};
+use crate::profile::SwerveProfile;
+use crate::UNITS_SCALE_FACTOR;
+
+const ARROW_COLOR: Srgba = ORANGE_RED;
+// Length of the drawn module vectors per unit of wheel speed, in m per m/s
+const ARROW_SCALE: f32 = 0.1;
+
+pub struct SwervePlugin;
+impl Plugin for SwervePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, drive_swerve.in_set(PhysicsSet::Drivetrain))
+            .add_systems(Update, draw_module_arrows);
+    }
+}
+
+/// Direction and speed of one wheel, in the robot's frame.
+#[derive(Clone, Copy, Default)]
+pub struct ModuleState {
+    /// rad
+    pub angle: f32,
+    /// m/s, negative when driving backwards
+    pub speed: f32,
+}
+
+impl ModuleState {
+    fn velocity(&self) -> Vec2 {
+        Vec2::from_angle(self.angle) * self.speed
+    }
+}
+
+/// Models a four-module swerve drivetrain. The robot's [`Velocity`] and [`AngularVelocity`] are taken
+/// as the chassis speeds it's commanded to reach, and replaced with the ones its modules achieve.
+#[derive(Component)]
+pub struct SwerveDrive {
+    pub profile: SwerveProfile,
+    pub modules: [ModuleState; 4],
+}
+
+impl SwerveDrive {
+    pub fn new(profile: SwerveProfile) -> Self {
+        Self {
+            profile,
+            modules: [ModuleState::default(); 4],
+        }
+    }
+
+    /// Where each module is on the robot, in m from its center: front left, front right, back left, back right.
+    pub fn module_offsets(&self) -> [Vec2; 4] {
+        let (x, y) = (self.profile.wheelbase / 2.0, self.profile.track_width / 2.0);
+        [
+            Vec2::new(x, y),
+            Vec2::new(x, -y),
+            Vec2::new(-x, y),
+            Vec2::new(-x, -y),
+        ]
+    }
+
+    /// Moves the modules toward the states that give the commanded chassis speeds, within their limits,
+    /// and returns the chassis speeds they actually give, all in the robot's frame.
+    fn update(&mut self, velocity: Vec2, angular_velocity: f32, dt: f32) -> (Vec2, f32) {
+        let offsets = self.module_offsets();
+        let profile = self.profile.clone();
+        let mut targets = offsets.map(|offset| velocity + angular_velocity * offset.perp());
+
+        // Slow everything down evenly if any module would have to go too fast, to keep the direction of travel
+        let fastest = targets.iter().map(|v| v.length()).fold(0.0, f32::max);
+        if fastest > profile.max_module_speed {
+            for target in &mut targets {
+                *target *= profile.max_module_speed / fastest;
+            }
+        }
+
+        for (module, target) in self.modules.iter_mut().zip(targets) {
+            let (mut angle, mut speed) = (target.to_angle(), target.length());
+            // Keep the current angle rather than snapping to 0 when stopping
+            if speed < 1e-3 {
+                angle = module.angle;
+            }
+            // Turning the wheel more than a quarter turn is never needed: drive it backwards instead
+            if norm_angle(angle - module.angle).abs() > std::f32::consts::FRAC_PI_2 {
+                angle += std::f32::consts::PI;
+                speed = -speed;
+            }
+
+            let max_steer = profile.max_steer_rate * dt;
+            let steer = norm_angle(angle - module.angle).clamp(-max_steer, max_steer);
+            module.angle = norm_angle(module.angle + steer);
+
+            // Only drive as fast as the wheel's current direction helps, so that it doesn't push sideways while steering
+            let wanted = speed * norm_angle(angle - module.angle).cos();
+            let max_change = profile.max_wheel_accel * dt;
+            module.speed = (module.speed + (wanted - module.speed).clamp(-max_change, max_change))
+                .clamp(-profile.max_module_speed, profile.max_module_speed);
+        }
+
+        // Best fit of the chassis speeds to the module velocities, which is exact when the
+        // modules agree; the modules are placed symmetrically around the center
+        let velocities = self.modules.map(|module| module.velocity());
+        let velocity = velocities.iter().sum::<Vec2>() / 4.0;
+        let angular_velocity = offsets
+            .iter()
+            .zip(velocities)
+            .map(|(offset, v)| offset.perp().dot(v))
+            .sum::<f32>()
+            / offsets
+                .iter()
+                .map(|offset| offset.length_squared())
+                .sum::<f32>();
+        (velocity, angular_velocity)
+    }
+}
+
+fn drive_swerve(
+    mut q: Query<(
+        &mut SwerveDrive,
+        &mut Velocity,
+        &mut AngularVelocity,
+        &Transform,
+    )>,
+    time: Res<Time>,
+) {
+    for (mut swerve, mut vel, mut avel, transform) in &mut q {
+        let heading = transform.rotation.to_euler(EulerRot::ZYX).0;
+        let to_robot = Vec2::from_angle(-heading);
+        let (velocity, angular_velocity) =
+            swerve.update(to_robot.rotate(vel.0), avel.0, time.delta_seconds());
+        vel.0 = Vec2::from_angle(heading).rotate(velocity);
+        avel.0 = angular_velocity;
+    }
+}
+
+/// Draws one of a swerve robot's module velocities, as a child of the robot.
+#[derive(Component)]
+pub struct ModuleArrow(pub usize);
+
+/// Spawns the module vectors of a swerve robot, to be added to its children.
+pub fn spawn_module_arrows(commands: &mut Commands) -> [Entity; 4] {
+    std::array::from_fn(|i| {
+        commands
+            .spawn((
+                ModuleArrow(i),
+                ShapeBundle::default(),
+                Stroke::new(ARROW_COLOR, 8.0 / UNITS_SCALE_FACTOR),
+            ))
+            .insert(Transform::from_xyz(0.0, 0.0, 1.15))
+            .id()
+    })
+}
+
+fn draw_module_arrows(
+    swerve_q: Query<&SwerveDrive>,
+    mut arrow_q: Query<(&ModuleArrow, &Parent, &mut Path)>,
+) {
+    for (arrow, parent, mut path) in &mut arrow_q {
+        let Ok(swerve) = swerve_q.get(parent.get()) else {
+            continue;
+        };
+        let start = swerve.module_offsets()[arrow.0];
+        let end = start + swerve.modules[arrow.0].velocity() * ARROW_SCALE;
+        *path = GeometryBuilder::build_as(&shapes::Line(start, end));
+    }
+}
@@ -0,0 +1,277 @@
+use bevy::prelude::*;
+
+use robot_planner::{norm_angle, Waypoint};
+
+// Long straight segments are split into pieces no longer than this, in m, so that the speed
+// profile can speed up and slow down along them
+const MAX_STEP: f32 = 0.2;
+// Robots that can only drive along their heading stop and turn in place at corners sharper than this, in rad
+const MAX_ROLLING_TURN: f32 = 0.35;
+
+/// Limits on the robot's motion along a trajectory.
+#[derive(Clone, Copy, Debug)]
+pub struct TrajectoryConstraints {
+    /// m/s
+    pub max_velocity: f32,
+    /// Along the path, in m/s²
+    pub max_acceleration: f32,
+    /// Sideways while turning, in m/s²
+    pub max_centripetal_acceleration: f32,
+    /// How fast a robot that can only drive along its heading turns in place, in rad/s.
+    /// `None` for robots that can drive in any direction.
+    pub max_turn_rate: Option<f32>,
+}
+
+/// Where the robot should be at a point in time.
+#[derive(Clone, Copy, Debug)]
+pub struct TrajectoryState {
+    /// s since the start of the trajectory
+    pub time: f32,
+    pub pos: Vec2,
+    pub velocity: Vec2,
+    pub acceleration: Vec2,
+    /// Direction of travel, in rad
+    pub heading: f32,
+    /// rad/s
+    pub angular_velocity: f32,
+}
+
+/// A path with a time-stamped speed profile that respects [`TrajectoryConstraints`].
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    states: Vec<TrajectoryState>,
+}
+
+impl Trajectory {
+    /// Time-parameterizes a path, starting at `initial_speed` and coming to a stop at its end.
+    /// If the robot can only drive along its heading, it first turns from `initial_heading` to
+    /// face along the path, and turns in place at sharp corners.
+    pub fn generate(
+        path: &[Waypoint],
+        constraints: &TrajectoryConstraints,
+        initial_speed: f32,
+        initial_heading: f32,
+    ) -> Self {
+        let (Some(turn_rate), Some(first)) = (constraints.max_turn_rate, path.first()) else {
+            return Self::generate_piece(path, constraints, initial_speed);
+        };
+
+        // Split the path at sharp corners
+        let mut pieces = vec![vec![*first]];
+        for (i, &wp) in path.iter().enumerate().skip(1) {
+            pieces.last_mut().unwrap().push(wp);
+            let sharp = path.get(i + 1).is_some_and(|next| {
+                let (into, out) = (wp.pos - path[i - 1].pos, next.pos - wp.pos);
+                into.angle_between(out).abs() > MAX_ROLLING_TURN
+            });
+            if sharp {
+                pieces.push(vec![wp]);
+            }
+        }
+
+        let mut trajectory = Self::at_rest(first.pos, initial_heading);
+        let mut speed = initial_speed;
+        for piece in pieces {
+            let next = Self::generate_piece(&piece, constraints, 0.0);
+            // Nowhere to go
+            if next.states.len() < 2 {
+                continue;
+            }
+            let turn = norm_angle(next.states[0].heading - trajectory.final_heading()).abs();
+            // Only keep rolling into the path if the robot already faces along it
+            let next = if turn <= MAX_ROLLING_TURN && speed > 0.0 {
+                Self::generate_piece(&piece, constraints, speed)
+            } else {
+                next
+            };
+            trajectory = trajectory.then(turn / turn_rate, next);
+            speed = 0.0;
+        }
+        trajectory
+    }
+
+    fn at_rest(pos: Vec2, heading: f32) -> Self {
+        Self {
+            states: vec![TrajectoryState {
+                time: 0.0,
+                pos,
+                velocity: Vec2::ZERO,
+                acceleration: Vec2::ZERO,
+                heading,
+                angular_velocity: 0.0,
+            }],
+        }
+    }
+
+    fn generate_piece(
+        path: &[Waypoint],
+        constraints: &TrajectoryConstraints,
+        initial_speed: f32,
+    ) -> Self {
+        // Split up long segments, keeping the speed limit of the segment each point ends
+        let mut points: Vec<(Vec2, Option<f32>)> = Vec::new();
+        for wp in path {
+            let Some(&(last, _)) = points.last() else {
+                points.push((wp.pos, wp.max_speed));
+                continue;
+            };
+            let len = (wp.pos - last).length();
+            if len < 1e-4 {
+                continue;
+            }
+            let steps = (len / MAX_STEP).ceil() as usize;
+            points.extend(
+                (1..=steps).map(|i| (last.lerp(wp.pos, i as f32 / steps as f32), wp.max_speed)),
+            );
+        }
+        let n = points.len();
+        if n < 2 {
+            let pos = points.first().map_or(Vec2::ZERO, |p| p.0);
+            return Self::at_rest(pos, 0.0);
+        }
+
+        let dirs: Vec<Vec2> = points
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].0).normalize())
+            .collect();
+        let lens: Vec<f32> = points
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].0).length())
+            .collect();
+
+        // Speed limit at each point, from the segment limits and the centripetal limit at corners
+        let mut speeds: Vec<f32> = (0..n)
+            .map(|i| {
+                let mut limit = constraints.max_velocity;
+                // A point is on both the segment before it and the one after it
+                for seg_limit in [points[i].1, points.get(i + 1).and_then(|p| p.1)]
+                    .into_iter()
+                    .flatten()
+                {
+                    limit = limit.min(seg_limit);
+                }
+                if i > 0 && i < n - 1 {
+                    let turn = dirs[i - 1].angle_between(dirs[i]).abs();
+                    let curvature = turn / ((lens[i - 1] + lens[i]) / 2.0);
+                    if curvature > 1e-6 {
+                        limit = limit
+                            .min((constraints.max_centripetal_acceleration / curvature).sqrt());
+                    }
+                }
+                limit
+            })
+            .collect();
+        speeds[0] = speeds[0].min(initial_speed.max(0.0));
+        speeds[n - 1] = 0.0;
+
+        // Limit how quickly the speed can rise going forwards, and how quickly it must fall going backwards
+        let accel = constraints.max_acceleration;
+        for i in 1..n {
+            speeds[i] = speeds[i].min((speeds[i - 1].powi(2) + 2.0 * accel * lens[i - 1]).sqrt());
+        }
+        for i in (0..n - 1).rev() {
+            speeds[i] = speeds[i].min((speeds[i + 1].powi(2) + 2.0 * accel * lens[i]).sqrt());
+        }
+
+        let tangent = |i: usize| match i {
+            0 => dirs[0],
+            i if i == n - 1 => dirs[n - 2],
+            i => (dirs[i - 1] + dirs[i]).normalize_or_zero(),
+        };
+        let mut time = 0.0;
+        let mut states: Vec<TrajectoryState> = (0..n)
+            .map(|i| {
+                if i > 0 {
+                    let v_sum = speeds[i - 1] + speeds[i];
+                    // Both ends at rest only happens between two sharp corners; accelerate then brake
+                    time += if v_sum > 1e-4 {
+                        2.0 * lens[i - 1] / v_sum
+                    } else {
+                        2.0 * (lens[i - 1] / accel).sqrt()
+                    };
+                }
+                TrajectoryState {
+                    time,
+                    pos: points[i].0,
+                    velocity: tangent(i) * speeds[i],
+                    acceleration: Vec2::ZERO,
+                    heading: tangent(i).to_angle(),
+                    angular_velocity: 0.0,
+                }
+            })
+            .collect();
+        for i in 0..n - 1 {
+            let dt = states[i + 1].time - states[i].time;
+            states[i].acceleration = (states[i + 1].velocity - states[i].velocity) / dt;
+            states[i].angular_velocity = norm_angle(states[i + 1].heading - states[i].heading) / dt;
+        }
+        Self { states }
+    }
+
+    /// Continues the trajectory with `next`, which starts where this one ends,
+    /// after staying stopped for `wait` s.
+    pub fn then(mut self, wait: f32, next: Trajectory) -> Self {
+        let offset = self.total_time() + wait;
+        self.states
+            .extend(next.states.into_iter().map(|state| TrajectoryState {
+                time: state.time + offset,
+                ..state
+            }));
+        self
+    }
+
+    /// Heading at the end of the trajectory, in rad.
+    pub fn final_heading(&self) -> f32 {
+        self.states.last().map_or(0.0, |s| s.heading)
+    }
+
+    /// Predicted time to drive the whole trajectory, in s.
+    pub fn total_time(&self) -> f32 {
+        self.states.last().map_or(0.0, |s| s.time)
+    }
+
+    /// The state at the given time, clamped to the ends of the trajectory.
+    pub fn sample(&self, time: f32) -> TrajectoryState {
+        let i = self.states.partition_point(|s| s.time <= time);
+        if i == 0 {
+            return self.states[0];
+        }
+        if i == self.states.len() {
+            let last = self.states[i - 1];
+            return TrajectoryState {
+                velocity: Vec2::ZERO,
+                acceleration: Vec2::ZERO,
+                angular_velocity: 0.0,
+                ..last
+            };
+        }
+        // Each piece between states is straight with constant acceleration along it
+        let (a, b) = (self.states[i - 1], self.states[i]);
+        let dt = b.time - a.time;
+        let tau = time - a.time;
+        let dir = (b.pos - a.pos).normalize_or_zero();
+        // Waiting or turning in place
+        if dir == Vec2::ZERO {
+            let turn = norm_angle(b.heading - a.heading);
+            return TrajectoryState {
+                time,
+                pos: a.pos,
+                velocity: Vec2::ZERO,
+                acceleration: Vec2::ZERO,
+                heading: a.heading + turn * tau / dt,
+                angular_velocity: turn / dt,
+            };
+        }
+        let (v_a, v_b) = (a.velocity.length(), b.velocity.length());
+        let accel = (v_b - v_a) / dt;
+        let dist = (v_a * tau + 0.5 * accel * tau * tau).min((b.pos - a.pos).length());
+        TrajectoryState {
+            time,
+            pos: a.pos + dir * dist,
+            velocity: dir * (v_a + accel * tau),
+            acceleration: a.acceleration,
+            heading: dir.to_angle(),
+            angular_velocity: a.angular_velocity,
+        }
+    }
+}
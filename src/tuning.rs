@@ -0,0 +1,176 @@
+use bevy::prelude::*;
+
+use crate::robot::{PathFollower, Robot, SelectedRobot, TargetFacer};
+
+// Name of each gain on the panel, and how much one press of an arrow key changes it
+const GAINS: [(&str, f32); 11] = [
+    ("Drive P", 0.5),
+    ("Drive I", 0.1),
+    ("Drive D", 0.05),
+    ("Drive max I (m/s)", 0.1),
+    ("Drive D filter (s)", 0.01),
+    ("Drive velocity FF", 0.05),
+    ("Turn P", 0.5),
+    ("Turn I", 0.1),
+    ("Turn D", 0.05),
+    ("Turn max I (rad/s)", 0.1),
+    ("Turn D filter (s)", 0.01),
+];
+// Holding shift changes gains this many times faster
+const COARSE_FACTOR: f32 = 10.0;
+
+pub struct TuningPlugin;
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TuningPanel {
+            open: false,
+            row: 0,
+        })
+        .add_systems(Startup, spawn_panel)
+        .add_systems(Update, (tune_gains, show_panel).chain());
+    }
+}
+
+/// The on-screen panel for tuning the controller gains of the selected robot.
+#[derive(Resource)]
+struct TuningPanel {
+    open: bool,
+    // The gain the arrow keys change
+    row: usize,
+}
+
+#[derive(Component)]
+struct PanelText;
+
+fn spawn_panel(mut commands: Commands) {
+    commands.spawn((
+        PanelText,
+        TextBundle {
+            visibility: Visibility::Hidden,
+            ..TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 16.0,
+                    ..Default::default()
+                },
+            )
+        }
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..Default::default()
+        })
+        .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+    ));
+}
+
+// The gains in the order they're listed on the panel
+fn gains_mut<'a>(follower: &'a mut PathFollower, facer: &'a mut TargetFacer) -> [&'a mut f32; 11] {
+    let (drive, turn) = (&mut follower.drive_gains, &mut facer.gains);
+    [
+        &mut drive.p,
+        &mut drive.i,
+        &mut drive.d,
+        &mut drive.max_integral,
+        &mut drive.derivative_filter,
+        &mut follower.velocity_ff,
+        &mut turn.p,
+        &mut turn.i,
+        &mut turn.d,
+        &mut turn.max_integral,
+        &mut turn.derivative_filter,
+    ]
+}
+
+// The same, for reading them
+fn gains(follower: &PathFollower, facer: &TargetFacer) -> [f32; 11] {
+    let (drive, turn) = (&follower.drive_gains, &facer.gains);
+    [
+        drive.p,
+        drive.i,
+        drive.d,
+        drive.max_integral,
+        drive.derivative_filter,
+        follower.velocity_ff,
+        turn.p,
+        turn.i,
+        turn.d,
+        turn.max_integral,
+        turn.derivative_filter,
+    ]
+}
+
+// Tuned gains last until the robot's profile is reloaded
+fn tune_gains(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut panel: ResMut<TuningPanel>,
+    selected: Res<SelectedRobot>,
+    mut q: Query<(&Robot, &mut PathFollower, &mut TargetFacer)>,
+) {
+    if keys.just_pressed(KeyCode::Tab) {
+        panel.open = !panel.open;
+    }
+    if !panel.open {
+        return;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        panel.row = (panel.row + GAINS.len() - 1) % GAINS.len();
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        panel.row = (panel.row + 1) % GAINS.len();
+    }
+
+    let direction = if keys.just_pressed(KeyCode::ArrowRight) {
+        1.0
+    } else if keys.just_pressed(KeyCode::ArrowLeft) {
+        -1.0
+    } else {
+        return;
+    };
+    let coarse = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let (name, step) = GAINS[panel.row];
+    let step = if coarse { step * COARSE_FACTOR } else { step };
+    for (robot, mut follower, mut facer) in &mut q {
+        if robot.0 != selected.0 {
+            continue;
+        }
+        let Some(gain) = gains_mut(&mut follower, &mut facer)
+            .into_iter()
+            .nth(panel.row)
+        else {
+            continue;
+        };
+        // Rounded so that stepping up and back down lands on the same value
+        *gain = ((*gain + direction * step).max(0.0) * 1e4).round() / 1e4;
+        eprintln!("Robot {}: {name} = {}", robot.0 + 1, *gain);
+    }
+}
+
+fn show_panel(
+    panel: Res<TuningPanel>,
+    selected: Res<SelectedRobot>,
+    robot_q: Query<(&Robot, &PathFollower, &TargetFacer)>,
+    mut text_q: Query<(&mut Text, &mut Visibility), With<PanelText>>,
+) {
+    let Ok((mut text, mut visibility)) = text_q.get_single_mut() else {
+        return;
+    };
+    let robot = robot_q.iter().find(|t| t.0 .0 == selected.0);
+    let (Some((_, follower, facer)), true) = (robot, panel.open) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+
+    let mut lines = vec![format!(
+        "Robot {} gains\nUp/Down: select, Left/Right: adjust (Shift: x{COARSE_FACTOR})",
+        selected.0 + 1
+    )];
+    for (row, ((name, _), gain)) in GAINS.iter().zip(gains(follower, facer)).enumerate() {
+        let cursor = if row == panel.row { ">" } else { " " };
+        lines.push(format!("{cursor} {name}: {gain}"));
+    }
+    text.sections[0].value = lines.join("\n");
+}
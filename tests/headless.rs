@@ -0,0 +1,38 @@
+//! The example scenario runs to completion without a window, the same way every time.
+
+use std::process::Command;
+
+use serde_json::Value;
+
+fn run_example() -> Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_robot-sim"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args([
+            "run",
+            "assets/example-scenario.json",
+            "assets/default-graph.json",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn every_robot_arrives() {
+    let results = run_example();
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    for result in results {
+        assert_eq!(result["arrived"], true, "{result}");
+    }
+}
+
+#[test]
+fn runs_are_deterministic() {
+    assert_eq!(run_example(), run_example());
+}